@@ -1,15 +1,38 @@
-use std::env;
+use std::{env, process};
 
 use bank_system::{
     balance_manager::BalanceManager,
-    storage::{Name, Storage},
+    error::BankError,
+    journal::{Journal, JournalRecord},
+    money::Money,
+    storage::{LoadMode, Name, Storage},
 };
 
 fn main() {
     // Загружаем текущее состояние банка из CSV-файла
     // Здесь демонстрация использования BufRead в методе load_data()
     // Файл читается построчно, и каждая строка преобразуется в (Name, Balance)
-    let mut storage = Storage::load_data("balance.csv");
+    let mut storage = match Storage::try_load_data("balance.csv", LoadMode::Lenient) {
+        Ok(report) => {
+            for e in &report.skipped {
+                eprintln!("Пропущена {}", e);
+            }
+            report.storage
+        }
+        Err(e) => {
+            eprintln!("Не удалось загрузить balance.csv: {}", e);
+            process::exit(1);
+        }
+    };
+
+    // Изменения после снимка хранятся в журнале — воспроизводим их
+    let mut journal = match Journal::open("balance.journal", &mut storage) {
+        Ok(journal) => journal,
+        Err(e) => {
+            eprintln!("Не удалось воспроизвести balance.journal: {}", e);
+            process::exit(1);
+        }
+    };
 
     // Получаем аргументы командной строки
     let args: Vec<String> = env::args().collect();
@@ -27,38 +50,50 @@ fn main() {
     match args[1].as_str() {
         "deposit" => {
             // Проверяем, что указан пользователь и сумма
-            if args.len() != 4 {
+            if !(4..=5).contains(&args.len()) {
                 eprintln!("Пример: add John 200");
                 return;
             }
             let name: Name = args[2].clone();
-            let amount: i64 = args[3].parse().expect("Сумма должна быть числом");
+            let amount = match parse_amount(&args[3..]) {
+                Ok(a) => a,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
 
             // Пытаемся пополнить баланс
-            match BalanceManager::deposit(&mut storage, &name, amount) {
-                Ok(_) => {
-                    println!("Пополнено: {} на {}", name, amount);
-                    // После изменения баланса сохраняем новое состояние в CSV
-                    storage.save("balance.csv");
-                }
+            let record = JournalRecord::Deposit {
+                account: name.clone(),
+                amount,
+            };
+            match execute(&mut storage, &mut journal, &record) {
+                Ok(_) => println!("Пополнено: {} на {}", name, amount),
                 Err(e) => println!("Ошибка: {}", e),
             }
         }
         "withdraw" => {
-            if args.len() != 4 {
+            if !(4..=5).contains(&args.len()) {
                 eprintln!("Пример: withdraw John 100");
                 return;
             }
             let name: Name = args[2].clone();
-            let amount: i64 = args[3].parse().expect("Сумма должна быть числом");
+            let amount = match parse_amount(&args[3..]) {
+                Ok(a) => a,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
 
             // Пытаемся снять деньги
-            match BalanceManager::withdraw(&mut storage, &name, amount) {
-                Ok(_) => {
-                    println!("Снято: {} на {}", name, amount);
-                    // Сохраняем изменения
-                    storage.save("balance.csv");
-                }
+            let record = JournalRecord::Withdraw {
+                account: name.clone(),
+                amount,
+            };
+            match execute(&mut storage, &mut journal, &record) {
+                Ok(_) => println!("Снято: {} на {}", name, amount),
                 Err(e) => println!("Ошибка: {}", e),
             }
         }
@@ -80,3 +115,21 @@ fn main() {
         }
     }
 }
+
+/// Применяет изменение и дописывает его в журнал.
+/// Если запись в журнал не удалась, изменение откатывается.
+fn execute(
+    storage: &mut Storage,
+    journal: &mut Journal,
+    record: &JournalRecord,
+) -> Result<(), BankError> {
+    storage.apply_durably(
+        |storage| record.apply(storage),
+        |_, _| journal.append(record),
+    )
+}
+
+/// Разбирает сумму операции ("100", "12.50" или "12.50 EUR"); сумма должна быть больше нуля
+fn parse_amount(args: &[String]) -> Result<Money, BankError> {
+    args.join(" ").parse::<Money>()?.ensure_positive()
+}
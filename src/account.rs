@@ -0,0 +1,284 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    clock::Timestamp,
+    customer::CustomerId,
+    error::BankError,
+    money::{Currency, Money},
+    storage::{Balance, Name},
+};
+
+/// Stable identifier of an account; unlike the name it is never reused
+pub type AccountId = u64;
+
+/// Column names of the account CSV format, written as its first row
+pub const CSV_HEADER: &str = "name,balance,id,owners,opened_at,status,limits";
+
+/// Whether an account can be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountStatus {
+    Active,
+    /// Blocked by the bank: money can come in but not go out
+    Frozen,
+    /// Unused for a long time: like a frozen account until reactivated
+    Dormant,
+    /// Closed with a zero balance and kept for its history; no money moves
+    Closed,
+}
+
+impl AccountStatus {
+    /// Whether money may leave an account in this state
+    pub fn allows_debits(self) -> bool {
+        self == AccountStatus::Active
+    }
+
+    /// Whether money may come into an account in this state
+    pub fn allows_credits(self) -> bool {
+        self != AccountStatus::Closed
+    }
+}
+
+impl fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountStatus::Active => write!(f, "active"),
+            AccountStatus::Frozen => write!(f, "frozen"),
+            AccountStatus::Dormant => write!(f, "dormant"),
+            AccountStatus::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+impl FromStr for AccountStatus {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(AccountStatus::Active),
+            "frozen" => Ok(AccountStatus::Frozen),
+            "dormant" => Ok(AccountStatus::Dormant),
+            "closed" => Ok(AccountStatus::Closed),
+            _ => Err(BankError::Parse(format!(
+                "неизвестный статус счёта '{}'",
+                s
+            ))),
+        }
+    }
+}
+
+/// Optional restrictions on how much can leave an account
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccountLimits {
+    /// How far below zero the balance may go
+    pub overdraft: Option<Money>,
+    /// The largest amount a single debit may take
+    pub per_transaction: Option<Money>,
+    /// The most that may leave the account within any 24 hours
+    pub daily: Option<Money>,
+}
+
+impl AccountLimits {
+    /// Each limit not set here is taken from `defaults`
+    pub fn or(self, defaults: AccountLimits) -> AccountLimits {
+        AccountLimits {
+            overdraft: self.overdraft.or(defaults.overdraft),
+            per_transaction: self.per_transaction.or(defaults.per_transaction),
+            daily: self.daily.or(defaults.daily),
+        }
+    }
+}
+
+/// Which spending limit a debit ran into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// `AccountLimits::per_transaction`
+    PerTransaction,
+    /// `AccountLimits::daily`, counted over the last 24 hours
+    Daily,
+}
+
+/// Stored as `key=amount` pairs separated by ';', e.g. `overdraft=100.00 RUB`;
+/// limits that are not set are left out
+impl fmt::Display for AccountLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let limits = [
+            ("overdraft", self.overdraft),
+            ("per_tx", self.per_transaction),
+            ("daily", self.daily),
+        ];
+        let mut separator = "";
+        for (key, limit) in limits {
+            if let Some(limit) = limit {
+                write!(f, "{}{}={}", separator, key, limit)?;
+                separator = ";";
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for AccountLimits {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut limits = AccountLimits::default();
+        for pair in s.split(';').filter(|p| !p.is_empty()) {
+            let invalid = || BankError::Parse(format!("некорректный лимит '{}'", pair));
+            let (key, amount) = pair.split_once('=').ok_or_else(invalid)?;
+            let amount = Some(amount.parse()?);
+            match key {
+                "overdraft" => limits.overdraft = amount,
+                "per_tx" => limits.per_transaction = amount,
+                "daily" => limits.daily = amount,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+/// An account together with its owners and settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    /// Assigned by `Storage` when the account is opened or loaded
+    pub id: AccountId,
+    /// The name transactions and the journal refer to the account by
+    pub name: Name,
+    /// Customers holding the account; a joint account has several
+    pub owners: Vec<CustomerId>,
+    pub opened_at: Timestamp,
+    pub status: AccountStatus,
+    pub balance: Balance,
+    pub limits: AccountLimits,
+}
+
+impl Account {
+    /// A new active account with a zero balance in `currency`;
+    /// the ID is assigned when the account is put into `Storage`
+    pub fn new(
+        name: Name,
+        owners: Vec<CustomerId>,
+        currency: Currency,
+        opened_at: Timestamp,
+    ) -> Account {
+        Account {
+            id: 0,
+            name,
+            owners,
+            opened_at,
+            status: AccountStatus::Active,
+            balance: Money::zero(currency),
+            limits: AccountLimits::default(),
+        }
+    }
+
+    /// The currency the account is kept in
+    pub fn currency(&self) -> Currency {
+        self.balance.currency()
+    }
+}
+
+/// Stored as a CSV row in the order of `CSV_HEADER`; owners are separated by ';'
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let owners: Vec<String> = self.owners.iter().map(|id| id.to_string()).collect();
+        write!(
+            f,
+            "{},{},{},{},{},{},{}",
+            self.name,
+            self.balance,
+            self.id,
+            owners.join(";"),
+            self.opened_at.unix(),
+            self.status,
+            self.limits
+        )
+    }
+}
+
+/// Parses a row in the order of `CSV_HEADER`, or an old "Name,Balance" row;
+/// an old row gets neither an ID nor owners yet
+impl FromStr for Account {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = line.trim().split(',').map(str::trim).collect();
+        if parts.len() != 2 && parts.len() != 7 {
+            return Err(format!("ожидалось 2 или 7 полей, найдено {}", parts.len()));
+        }
+
+        let name = parts[0];
+        if name.is_empty() {
+            return Err("пустое имя пользователя".to_string());
+        }
+        // Старые файлы без кода валюты хранят целые единицы валюты по умолчанию
+        let balance: Balance = parts[1]
+            .parse()
+            .map_err(|e| format!("некорректный баланс '{}': {}", parts[1], e))?;
+        let mut account = Account::new(
+            name.to_string(),
+            Vec::new(),
+            balance.currency(),
+            Timestamp::default(),
+        );
+        account.balance = balance;
+
+        if let [_, _, id, owners, opened_at, status, limits] = parts[..] {
+            account.id = id
+                .parse()
+                .map_err(|_| format!("некорректный номер счёта '{}'", id))?;
+            // Раньше здесь хранилось имя владельца: такие счета остаются
+            // без владельцев и получают их при загрузке
+            account.owners = owners
+                .split(';')
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .unwrap_or_default();
+            account.opened_at = opened_at
+                .parse()
+                .map(Timestamp::from_unix)
+                .map_err(|_| format!("некорректная дата открытия '{}'", opened_at))?;
+            account.status = status.parse().map_err(|e: BankError| e.to_string())?;
+            account.limits = limits.parse().map_err(|e: BankError| e.to_string())?;
+        }
+
+        Ok(account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::rub;
+
+    #[test]
+    fn test_csv_row_round_trip() {
+        let mut account = Account::new(
+            "alice-main".to_string(),
+            vec![1, 4],
+            Currency::RUB,
+            Timestamp::from_unix(1_714_550_400),
+        );
+        account.id = 3;
+        account.balance = rub(25);
+        account.limits.overdraft = Some(rub(100));
+        account.limits.daily = Some(rub(50));
+
+        let row = account.to_string();
+        assert_eq!(
+            row,
+            "alice-main,25.00 RUB,3,1;4,1714550400,active,overdraft=100.00 RUB;daily=50.00 RUB"
+        );
+        assert_eq!(row.parse::<Account>(), Ok(account.clone()));
+
+        // Старый формат "Name,Balance"
+        let old: Account = "John,100".parse().unwrap();
+        assert_eq!((old.id, old.owners.len()), (0, 0));
+        assert_eq!(old.balance, rub(100));
+        assert!("John,100,1".parse::<Account>().is_err());
+
+        account.status = AccountStatus::Frozen;
+        assert_eq!(account.to_string().parse::<Account>(), Ok(account));
+        assert!("John,1.00 RUB,1,1,0,blocked,".parse::<Account>().is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    account::{Account, AccountLimits},
+    customer::{Customer, CustomerId},
+    error::BankError,
+    hold::{Hold, HoldId},
+    journal::JournalRecord,
+    money::Currency,
+    statement::Posting,
+    storage::{Name, Snapshot, write_atomically},
+};
+
+/// Counter with the ID the next opened account gets
+pub const NEXT_ACCOUNT_ID: &str = "next_account_id";
+/// Counter with the ID the next hold gets
+pub const NEXT_HOLD_ID: &str = "next_hold_id";
+
+/// Where `Storage` keeps accounts, the customers holding them, holds on
+/// their money and the bank-wide default limits.
+///
+/// Mutations made through `put`/`remove` are visible immediately; `commit`
+/// makes everything changed so far durable, together with the `Snapshot`
+/// of the state `Storage` keeps itself.
+pub trait StorageBackend {
+    fn get(&self, name: &Name) -> Option<&Account>;
+    fn put(&mut self, account: Account);
+    fn remove(&mut self, name: &Name) -> Option<Account>;
+    fn iter(&self) -> Box<dyn Iterator<Item = &Account> + '_>;
+    fn get_customer(&self, id: CustomerId) -> Option<&Customer>;
+    fn put_customer(&mut self, customer: Customer);
+    fn remove_customer(&mut self, id: CustomerId) -> Option<Customer>;
+    /// Customers in the order of their IDs
+    fn customers(&self) -> Box<dyn Iterator<Item = &Customer> + '_>;
+    /// Limits for accounts in a currency that do not set their own
+    fn default_limits(&self) -> Box<dyn Iterator<Item = (Currency, AccountLimits)> + '_>;
+    fn put_default_limits(&mut self, currency: Currency, limits: AccountLimits);
+    fn get_hold(&self, id: HoldId) -> Option<&Hold>;
+    fn put_hold(&mut self, hold: Hold);
+    fn remove_hold(&mut self, id: HoldId) -> Option<Hold>;
+    /// Holds in the order of their IDs
+    fn holds(&self) -> Box<dyn Iterator<Item = &Hold> + '_>;
+    fn commit(&mut self, snapshot: &Snapshot<'_>) -> Result<(), BankError>;
+
+    /// Remembers an applied operation so it is persisted on the next `commit`.
+    /// Backends without a transaction history ignore it.
+    fn record(&mut self, _record: &JournalRecord) -> Result<(), BankError> {
+        Ok(())
+    }
+
+    /// Remembers a posting so it is persisted on the next `commit`.
+    /// Backends without an account history ignore it.
+    fn record_posting(&mut self, _posting: &Posting) {}
+
+    /// Forgets operations and postings recorded since the last `commit`,
+    /// after the changes they describe were rolled back
+    fn discard(&mut self) {}
+
+    /// Persisted value of a counter such as `NEXT_ACCOUNT_ID`, so IDs of
+    /// removed accounts and settled holds are not handed out again.
+    /// Backends that keep no counters return None.
+    fn counter(&self, _name: &str) -> Option<u64> {
+        None
+    }
+}
+
+/// Keeps accounts in memory only; `commit` is a no-op
+#[derive(Default)]
+pub struct MemoryBackend {
+    accounts: HashMap<Name, Account>,
+    customers: BTreeMap<CustomerId, Customer>,
+    default_limits: BTreeMap<Currency, AccountLimits>,
+    holds: BTreeMap<HoldId, Hold>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn get(&self, name: &Name) -> Option<&Account> {
+        self.accounts.get(name)
+    }
+
+    fn put(&mut self, account: Account) {
+        self.accounts.insert(account.name.clone(), account);
+    }
+
+    fn remove(&mut self, name: &Name) -> Option<Account> {
+        self.accounts.remove(name)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(self.accounts.values())
+    }
+
+    fn get_customer(&self, id: CustomerId) -> Option<&Customer> {
+        self.customers.get(&id)
+    }
+
+    fn put_customer(&mut self, customer: Customer) {
+        self.customers.insert(customer.id, customer);
+    }
+
+    fn remove_customer(&mut self, id: CustomerId) -> Option<Customer> {
+        self.customers.remove(&id)
+    }
+
+    fn customers(&self) -> Box<dyn Iterator<Item = &Customer> + '_> {
+        Box::new(self.customers.values())
+    }
+
+    fn default_limits(&self) -> Box<dyn Iterator<Item = (Currency, AccountLimits)> + '_> {
+        Box::new(self.default_limits.iter().map(|(c, l)| (*c, *l)))
+    }
+
+    fn put_default_limits(&mut self, currency: Currency, limits: AccountLimits) {
+        self.default_limits.insert(currency, limits);
+    }
+
+    fn get_hold(&self, id: HoldId) -> Option<&Hold> {
+        self.holds.get(&id)
+    }
+
+    fn put_hold(&mut self, hold: Hold) {
+        self.holds.insert(hold.id, hold);
+    }
+
+    fn remove_hold(&mut self, id: HoldId) -> Option<Hold> {
+        self.holds.remove(&id)
+    }
+
+    fn holds(&self) -> Box<dyn Iterator<Item = &Hold> + '_> {
+        Box::new(self.holds.values())
+    }
+
+    fn commit(&mut self, _snapshot: &Snapshot<'_>) -> Result<(), BankError> {
+        Ok(())
+    }
+}
+
+/// Keeps accounts in memory and rewrites its CSV file with the whole
+/// snapshot (see `Snapshot::write_csv`) on every commit
+pub struct CsvBackend {
+    path: PathBuf,
+    inner: MemoryBackend,
+}
+
+impl CsvBackend {
+    /// Creates a backend over `path` with the given initial accounts and customers
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        accounts: impl IntoIterator<Item = Account>,
+        customers: impl IntoIterator<Item = Customer>,
+    ) -> Self {
+        let mut inner = MemoryBackend::new();
+        for account in accounts {
+            inner.put(account);
+        }
+        for customer in customers {
+            inner.put_customer(customer);
+        }
+        CsvBackend {
+            path: path.as_ref().to_path_buf(),
+            inner,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl StorageBackend for CsvBackend {
+    fn get(&self, name: &Name) -> Option<&Account> {
+        self.inner.get(name)
+    }
+
+    fn put(&mut self, account: Account) {
+        self.inner.put(account);
+    }
+
+    fn remove(&mut self, name: &Name) -> Option<Account> {
+        self.inner.remove(name)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        self.inner.iter()
+    }
+
+    fn get_customer(&self, id: CustomerId) -> Option<&Customer> {
+        self.inner.get_customer(id)
+    }
+
+    fn put_customer(&mut self, customer: Customer) {
+        self.inner.put_customer(customer);
+    }
+
+    fn remove_customer(&mut self, id: CustomerId) -> Option<Customer> {
+        self.inner.remove_customer(id)
+    }
+
+    fn customers(&self) -> Box<dyn Iterator<Item = &Customer> + '_> {
+        self.inner.customers()
+    }
+
+    fn default_limits(&self) -> Box<dyn Iterator<Item = (Currency, AccountLimits)> + '_> {
+        self.inner.default_limits()
+    }
+
+    fn put_default_limits(&mut self, currency: Currency, limits: AccountLimits) {
+        self.inner.put_default_limits(currency, limits);
+    }
+
+    fn get_hold(&self, id: HoldId) -> Option<&Hold> {
+        self.inner.get_hold(id)
+    }
+
+    fn put_hold(&mut self, hold: Hold) {
+        self.inner.put_hold(hold);
+    }
+
+    fn remove_hold(&mut self, id: HoldId) -> Option<Hold> {
+        self.inner.remove_hold(id)
+    }
+
+    fn holds(&self) -> Box<dyn Iterator<Item = &Hold> + '_> {
+        self.inner.holds()
+    }
+
+    fn commit(&mut self, snapshot: &Snapshot<'_>) -> Result<(), BankError> {
+        let mut data = Vec::new();
+        snapshot.write_csv(&mut data, self)?;
+        write_atomically(&self.path, &data)?;
+        self.inner.commit(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        balance_manager::BalanceManager,
+        journal::{Journal, JournalRecord},
+        money::rub,
+        storage::{LoadMode, Storage, backup_path},
+        transaction::{Transaction, Transfer},
+        user_manager::UserManager,
+    };
+
+    #[test]
+    fn test_managers_work_with_memory_backend() {
+        let mut storage = Storage::with_backend(MemoryBackend::new());
+        UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
+        UserManager::add_user(&mut storage, "Bob".to_string()).unwrap();
+        BalanceManager::deposit(&mut storage, &"Alice".to_string(), rub(100)).unwrap();
+
+        Transfer {
+            from: "Alice".to_string(),
+            to: "Bob".to_string(),
+            amount: rub(40),
+        }
+        .apply(&mut storage)
+        .unwrap();
+
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Bob".to_string()),
+            Some(rub(40))
+        );
+        assert!(storage.commit().is_ok());
+    }
+
+    #[test]
+    fn test_csv_backend_commit_writes_file() {
+        let path = std::env::temp_dir().join(format!("bank-backend-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+
+        let john: Account = "John,5".parse().unwrap();
+        let mut storage = Storage::with_backend(CsvBackend::new(&path, [john], []));
+        BalanceManager::deposit(&mut storage, &"John".to_string(), rub(10)).unwrap();
+        storage.commit().unwrap();
+
+        let mut snapshot = Vec::new();
+        storage.write_csv(&mut snapshot).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), snapshot);
+
+        let reopened = Storage::open_csv(&path, LoadMode::Strict).unwrap().storage;
+        assert_eq!(
+            BalanceManager::get_balance(&reopened, &"John".to_string()),
+            Some(rub(15))
+        );
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_csv_backend_commit_keeps_journal_position() {
+        let path =
+            std::env::temp_dir().join(format!("bank-backend-wal-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let journal_path = format!("{}.journal", path);
+        fs::write(&path, "John,100\nAlice,0\n").unwrap();
+        let _ = fs::remove_file(&journal_path);
+
+        let mut storage = Storage::open_csv(&path, LoadMode::Strict).unwrap().storage;
+        let mut journal = Journal::open(&journal_path, &mut storage).unwrap();
+        let record = JournalRecord::Transfer {
+            from: "John".to_string(),
+            to: "Alice".to_string(),
+            amount: rub(10),
+        };
+        record.apply(&mut storage).unwrap();
+        journal.append(&record).unwrap();
+        journal.commit(&mut storage).unwrap();
+
+        // Запись журнала уже в снимке и не применяется второй раз
+        let mut reopened = Storage::try_load_data(&path, LoadMode::Strict)
+            .unwrap()
+            .storage;
+        Journal::open(&journal_path, &mut reopened).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&reopened, &"Alice".to_string()),
+            Some(rub(10))
+        );
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(backup_path(&path));
+        let _ = fs::remove_file(&journal_path);
+    }
+}
@@ -1,40 +1,144 @@
-use crate::storage::{Name, Storage};
+use crate::{
+    clock::Timestamp,
+    error::{AccountSide, BankError},
+    exchange::RateTable,
+    history::Receipt,
+    hold::{Hold, HoldId},
+    money::Money,
+    storage::{Name, Storage},
+    transaction::{Capture, Exchange, Transaction},
+};
 
 pub struct BalanceManager;
 
 impl BalanceManager {
     /// Gets the balance of a user
     /// Returns Some(balance) if user exists, None otherwise
-    pub fn get_balance(storage: &Storage, name: &Name) -> Option<i64> {
+    pub fn get_balance(storage: &Storage, name: &Name) -> Option<Money> {
         storage.get_balance_internal(name)
     }
 
+    /// Gets the balance a user can spend: the ledger balance less active holds
+    /// Returns Some(balance) if user exists, None otherwise
+    pub fn get_available_balance(storage: &Storage, name: &Name) -> Option<Money> {
+        storage.available_balance(name)
+    }
+
+    /// Gets the balance a user had at the given moment
+    /// Returns None if the account did not exist then
+    pub fn get_balance_at(storage: &Storage, name: &Name, at: Timestamp) -> Option<Money> {
+        storage.balance_at(name, at)
+    }
+
     /// Deposits amount into user's account
-    /// Returns Ok(()) if successful, Err if user not found
-    pub fn deposit(storage: &mut Storage, name: &Name, amount: i64) -> Result<(), String> {
+    /// Returns Ok(()) if successful, Err if user not found, the currency differs
+    /// or the balance would overflow
+    pub fn deposit(storage: &mut Storage, name: &Name, amount: Money) -> Result<(), BankError> {
         storage.deposit_internal(name, amount)
     }
 
     /// Withdraws amount from user's account
     /// Returns Ok(()) if successful, Err if user not found or insufficient funds
-    pub fn withdraw(storage: &mut Storage, name: &Name, amount: i64) -> Result<(), String> {
+    pub fn withdraw(storage: &mut Storage, name: &Name, amount: Money) -> Result<(), BankError> {
         storage.withdraw_internal(name, amount)
     }
+
+    /// Reserves amount on user's account until it is captured, released or
+    /// `expires_at` passes; the ledger balance does not change
+    /// Returns the hold's ID, Err if a withdrawal of the amount would be refused
+    pub fn authorize(
+        storage: &mut Storage,
+        name: &Name,
+        amount: Money,
+        expires_at: Option<Timestamp>,
+    ) -> Result<HoldId, BankError> {
+        storage.authorize_internal(name, amount, expires_at)
+    }
+
+    /// Takes the held money, all of it or `amount`, as a withdrawal or as a
+    /// transfer to `to`; the rest of the hold is released
+    /// Returns the receipt of the applied `Capture` transaction
+    pub fn capture(
+        storage: &mut Storage,
+        hold: HoldId,
+        amount: Option<Money>,
+        to: Option<&Name>,
+    ) -> Result<Receipt, BankError> {
+        let held = storage
+            .get_hold(hold)
+            .ok_or(BankError::HoldNotFound(hold))?;
+        Capture {
+            hold,
+            account: held.account.clone(),
+            amount: amount.unwrap_or(held.amount),
+            to: to.cloned(),
+        }
+        .apply(storage)
+    }
+
+    /// Converts amount from user's account into the currency of `to` at the
+    /// rate from `rates` and credits it there
+    /// Returns the receipt of the applied `Exchange` transaction, Err if
+    /// there is no rate for the pair or the debit would be refused
+    pub fn exchange(
+        storage: &mut Storage,
+        rates: &RateTable,
+        from: &Name,
+        to: &Name,
+        amount: Money,
+    ) -> Result<Receipt, BankError> {
+        let currency = storage
+            .get_account(to)
+            .map(|account| account.currency())
+            .ok_or_else(|| BankError::InvalidAccount {
+                side: AccountSide::Destination,
+                name: to.clone(),
+            })?;
+        let rate = rates.get(amount.currency(), currency)?;
+        Exchange::new(from.clone(), to.clone(), amount, rate)?.apply(storage)
+    }
+
+    /// Removes the hold without taking any money
+    pub fn release(storage: &mut Storage, hold: HoldId) -> Result<Hold, BankError> {
+        storage.release_internal(hold)
+    }
+
+    /// Removes every expired hold and returns them. Expired holds reserve
+    /// nothing even before they are removed.
+    pub fn expire_holds(storage: &mut Storage) -> Vec<Hold> {
+        storage.expire_holds_internal()
+    }
+
+    /// Holds on user's account that still reserve money
+    pub fn holds<'a>(storage: &'a Storage, name: &Name) -> Vec<&'a Hold> {
+        let now = storage.now();
+        storage
+            .holds()
+            .filter(|h| &h.account == name && h.is_active(now))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::user_manager::UserManager;
+    use crate::{
+        account::LimitKind,
+        clock::FixedClock,
+        ledger::exchange_account,
+        money::{Currency, rub},
+        transaction::Transfer,
+        user_manager::UserManager,
+    };
 
     #[test]
     fn test_get_balance() {
         let mut storage = Storage::new();
-        UserManager::add_user(&mut storage, "Alice".to_string());
+        UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
 
         assert_eq!(
             BalanceManager::get_balance(&storage, &"Alice".to_string()),
-            Some(0)
+            Some(rub(0))
         );
         assert_eq!(
             BalanceManager::get_balance(&storage, &"Nobody".to_string()),
@@ -45,27 +149,30 @@ mod tests {
     #[test]
     fn test_deposit_and_withdraw() {
         let mut storage = Storage::new();
-        UserManager::add_user(&mut storage, "Charlie".to_string());
+        UserManager::add_user(&mut storage, "Charlie".to_string()).unwrap();
 
         // Пополнение
-        assert!(BalanceManager::deposit(&mut storage, &"Charlie".to_string(), 200).is_ok());
+        assert!(BalanceManager::deposit(&mut storage, &"Charlie".to_string(), rub(200)).is_ok());
         assert_eq!(
             BalanceManager::get_balance(&storage, &"Charlie".to_string()),
-            Some(200)
+            Some(rub(200))
         );
 
         // Успешное снятие
-        assert!(BalanceManager::withdraw(&mut storage, &"Charlie".to_string(), 150).is_ok());
+        assert!(BalanceManager::withdraw(&mut storage, &"Charlie".to_string(), rub(150)).is_ok());
         assert_eq!(
             BalanceManager::get_balance(&storage, &"Charlie".to_string()),
-            Some(50)
+            Some(rub(50))
         );
 
         // Ошибка: недостаточно средств
-        assert!(BalanceManager::withdraw(&mut storage, &"Charlie".to_string(), 100).is_err());
+        assert!(matches!(
+            BalanceManager::withdraw(&mut storage, &"Charlie".to_string(), rub(100)),
+            Err(BankError::InsufficientFunds(_))
+        ));
         assert_eq!(
             BalanceManager::get_balance(&storage, &"Charlie".to_string()),
-            Some(50)
+            Some(rub(50))
         );
     }
 
@@ -74,10 +181,16 @@ mod tests {
         let mut storage = Storage::new();
 
         // Депозит несуществующему пользователю
-        assert!(BalanceManager::deposit(&mut storage, &"Dana".to_string(), 100).is_err());
+        assert!(matches!(
+            BalanceManager::deposit(&mut storage, &"Dana".to_string(), rub(100)),
+            Err(BankError::InvalidAccount { .. })
+        ));
 
         // Снятие у несуществующего пользователя
-        assert!(BalanceManager::withdraw(&mut storage, &"Dana".to_string(), 50).is_err());
+        assert!(matches!(
+            BalanceManager::withdraw(&mut storage, &"Dana".to_string(), rub(50)),
+            Err(BankError::InvalidAccount { .. })
+        ));
 
         // Баланс у несуществующего пользователя
         assert_eq!(
@@ -85,4 +198,325 @@ mod tests {
             None
         );
     }
+
+    #[test]
+    fn test_deposit_overflow() {
+        let mut storage = Storage::new();
+        UserManager::add_user(&mut storage, "Eve".to_string()).unwrap();
+        let max = Money::new(i64::MAX, Currency::RUB);
+        BalanceManager::deposit(&mut storage, &"Eve".to_string(), max).unwrap();
+
+        assert!(matches!(
+            BalanceManager::deposit(
+                &mut storage,
+                &"Eve".to_string(),
+                Money::new(1, Currency::RUB)
+            ),
+            Err(BankError::Overflow)
+        ));
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Eve".to_string()),
+            Some(max)
+        );
+    }
+
+    #[test]
+    fn test_currency_mismatch() {
+        let mut storage = Storage::new();
+        UserManager::add_user(&mut storage, "Fred".to_string()).unwrap();
+        let euros = Money::from_major(10, Currency::EUR).unwrap();
+
+        assert!(matches!(
+            BalanceManager::deposit(&mut storage, &"Fred".to_string(), euros),
+            Err(BankError::CurrencyMismatch { .. })
+        ));
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Fred".to_string()),
+            Some(rub(0))
+        );
+    }
+
+    #[test]
+    fn test_non_positive_amounts_rejected() {
+        let mut storage = Storage::new();
+        UserManager::add_user(&mut storage, "Gina".to_string()).unwrap();
+        BalanceManager::deposit(&mut storage, &"Gina".to_string(), rub(100)).unwrap();
+
+        for amount in [rub(0), rub(-500)] {
+            assert!(matches!(
+                BalanceManager::deposit(&mut storage, &"Gina".to_string(), amount),
+                Err(BankError::NonPositiveAmount(_))
+            ));
+            assert!(matches!(
+                BalanceManager::withdraw(&mut storage, &"Gina".to_string(), amount),
+                Err(BankError::NonPositiveAmount(_))
+            ));
+        }
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Gina".to_string()),
+            Some(rub(100))
+        );
+    }
+
+    #[test]
+    fn test_get_balance_at() {
+        let at = |date: &str| date.parse::<Timestamp>().unwrap();
+        let hanna = "Hanna".to_string();
+        let mut storage = Storage::new();
+
+        storage.set_clock(FixedClock(at("2024-05-01")));
+        UserManager::add_user(&mut storage, hanna.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &hanna, rub(100)).unwrap();
+        storage.set_clock(FixedClock(at("2024-06-15")));
+        BalanceManager::withdraw(&mut storage, &hanna, rub(40)).unwrap();
+        storage.set_clock(FixedClock(at("2024-07-01")));
+        BalanceManager::withdraw(&mut storage, &hanna, rub(60)).unwrap();
+        UserManager::remove_user(&mut storage, &hanna).unwrap();
+
+        assert_eq!(
+            BalanceManager::get_balance_at(&storage, &hanna, at("2024-04-30")),
+            None
+        );
+        assert_eq!(
+            BalanceManager::get_balance_at(&storage, &hanna, at("2024-05-31 23:59:59")),
+            Some(rub(100))
+        );
+        assert_eq!(
+            BalanceManager::get_balance_at(&storage, &hanna, at("2024-06-15")),
+            Some(rub(60))
+        );
+        assert_eq!(
+            BalanceManager::get_balance_at(&storage, &hanna, at("2024-07-02")),
+            None
+        );
+
+        let may = storage.snapshot_at(at("2024-05-31"));
+        assert_eq!(BalanceManager::get_balance(&may, &hanna), Some(rub(100)));
+        assert_eq!(
+            may.postings().iter().filter(|p| p.account == hanna).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_holds_reduce_available_balance() {
+        let mut storage = Storage::new();
+        let (alice, bob) = ("Alice".to_string(), "Bob".to_string());
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        UserManager::add_user(&mut storage, bob.clone()).unwrap();
+        storage.set_clock(FixedClock("2024-05-01 10:00:00".parse().unwrap()));
+        BalanceManager::deposit(&mut storage, &alice, rub(100)).unwrap();
+
+        let hotel = BalanceManager::authorize(&mut storage, &alice, rub(60), None).unwrap();
+        let taxi = BalanceManager::authorize(
+            &mut storage,
+            &alice,
+            rub(30),
+            Some("2024-05-02 10:00:00".parse().unwrap()),
+        )
+        .unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(100))
+        );
+        assert_eq!(
+            BalanceManager::get_available_balance(&storage, &alice),
+            Some(rub(10))
+        );
+        // Заблокированные деньги не снять и не заблокировать повторно
+        assert!(matches!(
+            BalanceManager::withdraw(&mut storage, &alice, rub(11)),
+            Err(BankError::InsufficientFunds(_))
+        ));
+        assert!(BalanceManager::authorize(&mut storage, &alice, rub(11), None).is_err());
+
+        // Частичное списание переводит деньги и снимает остаток блокировки
+        let receipt =
+            BalanceManager::capture(&mut storage, hotel, Some(rub(45)), Some(&bob)).unwrap();
+        assert_eq!(BalanceManager::get_balance(&storage, &alice), Some(rub(55)));
+        assert_eq!(BalanceManager::get_balance(&storage, &bob), Some(rub(45)));
+        assert_eq!(
+            BalanceManager::get_available_balance(&storage, &alice),
+            Some(rub(25))
+        );
+        assert!(matches!(
+            BalanceManager::capture(&mut storage, hotel, None, None),
+            Err(BankError::HoldNotFound(_))
+        ));
+        assert!(BalanceManager::capture(&mut storage, taxi, Some(rub(31)), None).is_err());
+
+        // Отмена списания возвращает деньги, но не блокировку
+        storage.reverse(receipt.id, None).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(100))
+        );
+
+        // Истёкшая блокировка ничего не держит и не списывается
+        storage.set_clock(FixedClock("2024-05-02 10:00:00".parse().unwrap()));
+        assert_eq!(
+            BalanceManager::get_available_balance(&storage, &alice),
+            Some(rub(100))
+        );
+        assert!(matches!(
+            BalanceManager::capture(&mut storage, taxi, None, None),
+            Err(BankError::HoldExpired(_))
+        ));
+        assert_eq!(BalanceManager::expire_holds(&mut storage).len(), 1);
+        assert!(BalanceManager::release(&mut storage, taxi).is_err());
+
+        let coffee = BalanceManager::authorize(&mut storage, &alice, rub(5), None).unwrap();
+        assert_eq!(BalanceManager::holds(&storage, &alice).len(), 1);
+        BalanceManager::release(&mut storage, coffee).unwrap();
+        assert_eq!(
+            BalanceManager::get_available_balance(&storage, &alice),
+            Some(rub(100))
+        );
+        assert!(storage.trial_balance().unwrap().is_balanced());
+    }
+
+    #[test]
+    fn test_failed_capture_keeps_hold() {
+        let mut storage = Storage::new();
+        let alice = "Alice".to_string();
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(100)).unwrap();
+        let hold = BalanceManager::authorize(&mut storage, &alice, rub(40), None).unwrap();
+
+        // Получателя нет: списание откатывается вместе со снятием блокировки
+        assert!(
+            BalanceManager::capture(&mut storage, hold, None, Some(&"Nobody".to_string())).is_err()
+        );
+        assert_eq!(
+            BalanceManager::get_available_balance(&storage, &alice),
+            Some(rub(60))
+        );
+        assert!(storage.get_hold(hold).is_some());
+        BalanceManager::capture(&mut storage, hold, None, None).unwrap();
+        assert_eq!(BalanceManager::get_balance(&storage, &alice), Some(rub(60)));
+    }
+
+    #[test]
+    fn test_account_with_holds_is_not_closed() {
+        let mut storage = Storage::new();
+        let (alice, bob) = ("Alice".to_string(), "Bob".to_string());
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        UserManager::add_user(&mut storage, bob.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(100)).unwrap();
+        let hold = BalanceManager::authorize(&mut storage, &alice, rub(40), None).unwrap();
+
+        for result in [
+            UserManager::close_account(&mut storage, &alice, Some(&bob)),
+            UserManager::remove_user(&mut storage, &alice),
+        ] {
+            assert!(matches!(result, Err(BankError::AccountHasHolds(name)) if name == alice));
+        }
+        assert_eq!(BalanceManager::get_balance(&storage, &bob), Some(rub(0)));
+
+        // Блокировка по-прежнему списывается, после чего счёт закрывается
+        BalanceManager::capture(&mut storage, hold, None, None).unwrap();
+        assert_eq!(
+            UserManager::close_account(&mut storage, &alice, Some(&bob)).unwrap(),
+            rub(60)
+        );
+        assert_eq!(BalanceManager::get_balance(&storage, &bob), Some(rub(60)));
+    }
+
+    #[test]
+    fn test_holds_count_toward_daily_limit() {
+        let mut storage = Storage::new();
+        let alice = "Alice".to_string();
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(1000)).unwrap();
+        UserManager::set_limit(&mut storage, &alice, LimitKind::Daily, Some(rub(100))).unwrap();
+
+        let hotel = BalanceManager::authorize(&mut storage, &alice, rub(60), None).unwrap();
+        assert!(matches!(
+            BalanceManager::authorize(&mut storage, &alice, rub(50), None),
+            Err(BankError::SpendingLimitExceeded {
+                kind: LimitKind::Daily,
+                remaining,
+                ..
+            }) if remaining == rub(40)
+        ));
+        let taxi = BalanceManager::authorize(&mut storage, &alice, rub(40), None).unwrap();
+
+        // Блокировки, прошедшие лимит, списываются без повторного отказа
+        BalanceManager::capture(&mut storage, hotel, None, None).unwrap();
+        BalanceManager::capture(&mut storage, taxi, None, None).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(900))
+        );
+    }
+
+    #[test]
+    fn test_exchange_between_currencies() {
+        let mut storage = Storage::new();
+        let (alice, euros) = ("Alice".to_string(), "alice-eur".to_string());
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        let owner = storage.get_account(&alice).unwrap().owners[0];
+        UserManager::open_account(&mut storage, euros.clone(), &[owner], Currency::EUR).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(2000)).unwrap();
+        let eur = |minor| Money::new(minor, Currency::EUR);
+
+        // Без обмена деньги между валютами не переходят
+        assert!(matches!(
+            Transfer {
+                from: alice.clone(),
+                to: euros.clone(),
+                amount: rub(100),
+            }
+            .apply(&mut storage),
+            Err(BankError::CurrencyMismatch { .. })
+        ));
+        assert!(matches!(
+            BalanceManager::deposit(&mut storage, &euros, rub(100)),
+            Err(BankError::CurrencyMismatch { .. })
+        ));
+
+        let rates: RateTable = "RUB/EUR 0.0099".parse().unwrap();
+        let receipt =
+            BalanceManager::exchange(&mut storage, &rates, &alice, &euros, rub(1000)).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(1000))
+        );
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &euros),
+            Some(eur(990))
+        );
+        assert_eq!(
+            storage
+                .history()
+                .get(receipt.id)
+                .unwrap()
+                .record
+                .to_string(),
+            "exchange,Alice,alice-eur,1000.00 RUB,9.90 EUR,RUB/EUR 0.0099"
+        );
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &exchange_account(Currency::EUR)),
+            Some(eur(-990))
+        );
+        assert!(storage.trial_balance().unwrap().is_balanced());
+
+        assert!(matches!(
+            BalanceManager::exchange(&mut storage, &rates, &euros, &alice, eur(100)),
+            Err(BankError::RateNotFound { .. })
+        ));
+        assert!(matches!(
+            BalanceManager::exchange(&mut storage, &rates, &alice, &euros, rub(1001)),
+            Err(BankError::InsufficientFunds(_))
+        ));
+
+        // Отмена возвращает ровно обменянные суммы
+        storage.reverse(receipt.id, None).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(2000))
+        );
+        assert_eq!(BalanceManager::get_balance(&storage, &euros), Some(eur(0)));
+        assert!(storage.trial_balance().unwrap().is_balanced());
+    }
 }
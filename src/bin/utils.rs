@@ -1,27 +1,90 @@
-use std::io::{self, BufRead, Write};
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    process,
+};
 
 use bank_system::{
+    account::{Account, AccountStatus, LimitKind},
     balance_manager::BalanceManager,
-    storage::{Name, Storage},
-    transaction::{Deposit, Transaction, Transfer},
+    clock::Timestamp,
+    customer::CustomerId,
+    error::BankError,
+    exchange::RateTable,
+    history::{Receipt, TxId},
+    hold::HoldId,
+    journal::{Journal, JournalRecord},
+    ledger::{AccountGroup, Side, TrialBalance, is_internal},
+    money::{Currency, Money},
+    statement::{PostingKind, Statement},
+    storage::{LoadMode, LoadSource, Name, Storage},
+    transaction::{Booking, Capture, Deposit, Exchange, Transaction, Transfer, Withdraw},
     user_manager::UserManager,
 };
 
+#[cfg(feature = "sqlite")]
+use bank_system::{history::TxLog, sqlite_backend::SqliteBackend};
+
 const FILE_NAME: &str = "balance.csv";
+const JOURNAL_NAME: &str = "balance.journal";
+/// Курсы обмена, по одному на строку: "EUR/RUB 98.50"
+const RATES_NAME: &str = "rates.txt";
+
+/// Куда записываются применённые изменения
+enum Persistence {
+    /// CSV-снимок и журнал изменений
+    Journal(Journal),
+    /// Встроенная база SQLite: каждое изменение фиксируется транзакцией
+    #[cfg(feature = "sqlite")]
+    Database,
+}
 
 fn main() {
-    let mut storage = Storage::load_data("balance.csv");
+    let args: Vec<String> = env::args().collect();
+    let (mut storage, mut persistence) = open_storage(&args);
 
     println!("=== Bank CLI Utils ===");
     println!("Команды:");
     println!("  add <name> <balance>      - добавить пользователя");
-    println!("  remove <name>             - удалить пользователя");
+    println!("  remove <name>             - удалить пустой действующий счёт");
     println!("  list                      - показать всех пользователей");
+    println!("  info <name>               - реквизиты счёта");
+    println!("  customer <full name>      - зарегистрировать клиента");
+    println!(
+        "  open <name> <id[,id...]> [currency] - открыть счёт клиента (совместный — несколько id)"
+    );
+    println!("  accounts <customer-id>    - счета клиента и общий остаток");
+    println!("  freeze <name>             - заморозить счёт: списания запрещены");
+    println!("  unfreeze <name>           - разморозить счёт");
+    println!("  close <name> [payout]     - закрыть счёт, выплатив остаток на счёт payout");
+    println!("  overdraft <name> <amount> - разрешить минус до amount (0 — запретить)");
+    println!("  limit <name> <tx|day> <amount> - лимит операции или суток (0 — снять)");
+    println!("  default-limit <tx|day> <amount> - лимит банка для счетов без своего");
     println!("  deposit <name> <amount>   - пополнить баланс");
     println!("  withdraw <name> <amount>  - снять со счёта");
-    println!("  balance <name>            - показать баланс");
+    println!("  balance <name> [--at <date>] - показать баланс (на конец дня <date>)");
     println!("  transfer <from> <to> <amount> - перевести деньги");
+    println!(
+        "  exchange <from> <to> <amount> - обменять в валюту счёта <to> по курсу из {}",
+        RATES_NAME
+    );
+    println!("  hold <name> <amount> [--until <date>] - заблокировать сумму до списания");
+    println!("  capture <hold-id> [amount] [--to <name>] - списать заблокированное");
+    println!("  release <hold-id>         - снять блокировку");
+    println!("  holds <name>              - действующие блокировки счёта");
+    println!("  fee <name> <amount>       - списать комиссию в доход банка");
+    println!("  interest <name> <amount>  - начислить проценты за счёт банка");
+    println!("  reverse <tx-id>           - отменить транзакцию");
+    println!("  statement <name> [from] [to] - выписка по счёту (даты ГГГГ-ММ-ДД)");
+    println!("  trial                     - оборотно-сальдовая ведомость банка");
+    println!("  report                    - остатки по типам счетов");
+    println!("  compact                   - перенести журнал в снимок");
     println!("  exit                      - выйти");
+    println!(
+        "Суммы: 100, 12.50 или 12.50 EUR (по умолчанию {})",
+        Currency::default()
+    );
+    println!("Комментарий к транзакции пишется после '#': deposit John 100 # зарплата");
 
     let stdin = io::stdin();
     let mut stdout = io::stdout();
@@ -35,31 +98,44 @@ fn main() {
             break; // EOF
         }
 
-        let args: Vec<&str> = input.split_whitespace().collect();
+        // Всё после '#' — комментарий к транзакции
+        let (command, memo) = match input.split_once('#') {
+            Some((command, memo)) => (command, Some(memo.trim())),
+            None => (input.as_str(), None),
+        };
+        let args: Vec<&str> = command.split_whitespace().collect();
         if args.is_empty() {
             continue;
         }
 
         match args[0] {
             "add" => {
-                if args.len() != 3 {
+                if !(3..=4).contains(&args.len()) {
                     println!("Пример: add John 100");
                     continue;
                 }
                 let name: Name = args[1].to_string();
-                let balance: i64 = match args[2].parse() {
+                let balance: Money = match args[2..].join(" ").parse() {
                     Ok(b) => b,
-                    Err(_) => {
-                        println!("Сумма должна быть числом");
+                    Err(e) => {
+                        println!("{}", e);
                         continue;
                     }
                 };
-                if UserManager::add_user(&mut storage, name.clone()).is_some() {
-                    let _ = BalanceManager::deposit(&mut storage, &name, balance);
-                    println!("Пользователь {} добавлен с балансом {}", name, balance);
-                    storage.save(FILE_NAME);
-                } else {
-                    println!("Пользователь {} уже существует", name);
+                let record = JournalRecord::AddUser {
+                    name: name.clone(),
+                    balance,
+                    opened_at: None,
+                };
+                let added = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| record.apply(storage),
+                    |storage, _| stamped(storage, &record),
+                );
+                match added {
+                    Ok(_) => println!("Пользователь {} добавлен с балансом {}", name, balance),
+                    Err(e) => println!("Ошибка: {}", e),
                 }
             }
             "remove" => {
@@ -67,12 +143,16 @@ fn main() {
                     println!("Пример: remove John");
                     continue;
                 }
-                let name = args[1];
-                if UserManager::remove_user(&mut storage, &name.to_string()).is_some() {
-                    println!("Пользователь {} удалён", name);
-                    storage.save(FILE_NAME);
-                } else {
-                    println!("Пользователь {} не найден", name);
+                let name = args[1].to_string();
+                let removed = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| UserManager::remove_user(storage, &name),
+                    |_, _| JournalRecord::RemoveUser { name: name.clone() },
+                );
+                match removed {
+                    Ok(_) => println!("Пользователь {} удалён", name),
+                    Err(e) => println!("Ошибка: {}", e),
                 }
             }
             "list" => {
@@ -80,20 +160,254 @@ fn main() {
                     println!("Пример: list");
                     continue;
                 }
-                for (name, balance) in storage.get_all() {
-                    println!("{}: {}", name, balance);
+                for account in storage.get_all().filter(|a| !is_internal(&a.name)) {
+                    println!(
+                        "{}: {}{}",
+                        account.name,
+                        account.balance,
+                        status_mark(account.status)
+                    );
+                }
+            }
+            "info" => {
+                if args.len() != 2 {
+                    println!("Пример: info John");
+                    continue;
+                }
+                match UserManager::get_account(&storage, &args[1].to_string()) {
+                    Some(account) => print_account(&storage, account),
+                    None => println!("Пользователь {} не найден", args[1]),
+                }
+            }
+            "customer" => {
+                if args.len() < 2 {
+                    println!("Пример: customer Анна Петрова");
+                    continue;
+                }
+                let name = args[1..].join(" ");
+                let added = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| UserManager::add_customer(storage, name),
+                    |storage, id| {
+                        JournalRecord::AddCustomer(
+                            UserManager::get_customer(storage, *id).unwrap().clone(),
+                        )
+                    },
+                );
+                match added {
+                    Ok(id) => {
+                        let customer = UserManager::get_customer(&storage, id).unwrap();
+                        println!(
+                            "Клиент {} зарегистрирован под номером {}",
+                            customer.name, id
+                        );
+                    }
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "open" => {
+                if !(3..=4).contains(&args.len()) {
+                    println!("Пример: open anna-eur 3 EUR или open family 3,4");
+                    continue;
+                }
+                let owners: Result<Vec<CustomerId>, _> =
+                    args[2].split(',').map(str::parse).collect();
+                let Ok(owners) = owners else {
+                    println!("Некорректные номера клиентов '{}'", args[2]);
+                    continue;
+                };
+                let currency = match args.get(3).map(|c| c.parse::<Currency>()) {
+                    None => Currency::default(),
+                    Some(Ok(currency)) => currency,
+                    Some(Err(e)) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                let record = JournalRecord::OpenAccount {
+                    name: args[1].to_string(),
+                    owners,
+                    currency,
+                    opened_at: None,
+                };
+                let opened = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| record.apply(storage),
+                    |storage, _| stamped(storage, &record),
+                );
+                match opened {
+                    Ok(_) => println!("Счёт {} открыт", args[1]),
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "accounts" => {
+                let Some(Ok(id)) = args.get(1).map(|id| id.parse::<CustomerId>()) else {
+                    println!("Пример: accounts 3");
+                    continue;
+                };
+                match UserManager::customer_position(&storage, id) {
+                    Ok(position) => {
+                        let customer = UserManager::get_customer(&storage, id).unwrap();
+                        println!("Клиент #{} {}", id, customer.name);
+                        for account in UserManager::accounts_of(&storage, id) {
+                            let joint = if account.owners.len() > 1 {
+                                " (совместный)"
+                            } else {
+                                ""
+                            };
+                            println!(
+                                "  {}: {}{}{}",
+                                account.name,
+                                account.balance,
+                                joint,
+                                status_mark(account.status)
+                            );
+                        }
+                        for total in position.values() {
+                            println!("Итого: {}", total);
+                        }
+                    }
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "freeze" | "unfreeze" => {
+                if args.len() != 2 {
+                    println!("Пример: {} John", args[0]);
+                    continue;
+                }
+                let (status, done) = if args[0] == "freeze" {
+                    (AccountStatus::Frozen, "заморожен")
+                } else {
+                    (AccountStatus::Active, "разморожен")
+                };
+                let record = JournalRecord::SetStatus {
+                    name: args[1].to_string(),
+                    status,
+                };
+                let changed = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| record.apply(storage),
+                    |_, _| record.clone(),
+                );
+                match changed {
+                    Ok(_) => println!("Счёт {} {}", args[1], done),
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "close" => {
+                if !(2..=3).contains(&args.len()) {
+                    println!("Пример: close John или close John Alice");
+                    continue;
+                }
+                let name = args[1].to_string();
+                let payout = args.get(2).map(|p| p.to_string());
+                let closed = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| UserManager::close_account(storage, &name, payout.as_ref()),
+                    |_, _| JournalRecord::CloseAccount {
+                        name: name.clone(),
+                        payout: payout.clone(),
+                    },
+                );
+                match closed {
+                    Ok(balance) => match &payout {
+                        Some(payout) if !balance.is_zero() => {
+                            println!("Счёт {} закрыт, {} выплачено на {}", name, balance, payout)
+                        }
+                        _ => println!("Счёт {} закрыт", name),
+                    },
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "overdraft" => {
+                if !(3..=4).contains(&args.len()) {
+                    println!("Пример: overdraft John 500");
+                    continue;
+                }
+                let name = args[1].to_string();
+                let limit: Money = match args[2..].join(" ").parse() {
+                    Ok(limit) => limit,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                let limit = Some(limit).filter(|l| !l.is_zero());
+                let changed = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| UserManager::set_overdraft(storage, &name, limit),
+                    |storage, _| set_limits(storage, &name),
+                );
+                match changed {
+                    Ok(()) => match limit {
+                        Some(limit) => println!("Овердрафт по счёту {}: {}", name, limit),
+                        None => println!("Овердрафт по счёту {} отключён", name),
+                    },
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "limit" => {
+                let Some((kind, limit)) = args.get(2..).and_then(parse_limit) else {
+                    println!("Пример: limit John day 1000 или limit John tx 500");
+                    continue;
+                };
+                let name = args[1].to_string();
+                let changed = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| UserManager::set_limit(storage, &name, kind, limit),
+                    |storage, _| set_limits(storage, &name),
+                );
+                match changed {
+                    Ok(()) => print_limit(kind, &name, limit),
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "default-limit" => {
+                let Some((kind, limit)) = parse_limit(&args[1..]) else {
+                    println!("Пример: default-limit day 1000 или default-limit tx 100 EUR");
+                    continue;
+                };
+                // Снятый лимит задаётся нулём в валюте, для которой его снимают
+                let currency = match args.get(3) {
+                    Some(code) => code.parse().unwrap_or_default(),
+                    None => Currency::default(),
+                };
+                let mut limits = storage
+                    .default_limits()
+                    .find(|(c, _)| *c == currency)
+                    .map(|(_, limits)| limits)
+                    .unwrap_or_default();
+                match kind {
+                    LimitKind::PerTransaction => limits.per_transaction = limit,
+                    LimitKind::Daily => limits.daily = limit,
+                }
+                let changed = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| storage.set_default_limits(currency, limits),
+                    |_, _| JournalRecord::DefaultLimits { currency, limits },
+                );
+                match changed {
+                    Ok(()) => print_limit(kind, "банка", limit),
+                    Err(e) => println!("Ошибка: {}", e),
                 }
             }
             "deposit" => {
-                if args.len() != 3 {
-                    println!("Пример: deposit John 100");
+                if !(3..=4).contains(&args.len()) {
+                    println!("Пример: deposit John 100.50");
                     continue;
                 }
                 let name = args[1].to_string();
-                let amount: i64 = match args[2].parse() {
+                let amount = match parse_amount(&args[2..]) {
                     Ok(a) => a,
-                    Err(_) => {
-                        println!("Сумма должна быть числом");
+                    Err(e) => {
+                        println!("{}", e);
                         continue;
                     }
                 };
@@ -103,57 +417,220 @@ fn main() {
                     amount,
                 };
                 // Применяем транзакцию
-                match tx.apply(&mut storage) {
-                    Ok(_) => {
-                        println!("Транзакция: депозит {} на {}", name, amount);
-                        storage.save(FILE_NAME);
-                    }
-                    Err(e) => println!("Ошибка транзакции: {:?}", e),
+                if execute(&mut storage, &mut persistence, &tx, memo) {
+                    println!("Транзакция: депозит {} на {}", name, amount);
                 }
             }
             "withdraw" => {
-                if args.len() != 3 {
+                if !(3..=4).contains(&args.len()) {
                     println!("Пример: withdraw John 100");
                     continue;
                 }
                 let name = args[1].to_string();
-                let amount: i64 = match args[2].parse() {
+                let amount = match parse_amount(&args[2..]) {
+                    Ok(a) => a,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                let tx = Withdraw {
+                    account: name.clone(),
+                    amount,
+                };
+                if execute(&mut storage, &mut persistence, &tx, memo) {
+                    println!("С баланса пользователя {} снято {}", name, amount);
+                }
+            }
+            "hold" => {
+                let (amount, until) = match args.iter().position(|a| *a == "--until") {
+                    Some(pos) => (&args[2.min(pos)..pos], args.get(pos + 1)),
+                    None => (args.get(2..).unwrap_or_default(), None),
+                };
+                if !(1..=2).contains(&amount.len()) {
+                    println!("Пример: hold John 100 или hold John 100 --until 2024-06-01");
+                    continue;
+                }
+                let name = args[1].to_string();
+                let amount = match parse_amount(amount) {
                     Ok(a) => a,
-                    Err(_) => {
-                        println!("Сумма должна быть числом");
+                    Err(e) => {
+                        println!("{}", e);
                         continue;
                     }
                 };
-                match BalanceManager::withdraw(&mut storage, &name, amount) {
-                    Ok(_) => {
-                        println!("С баланса пользователя {} снято {}", name, amount);
-                        storage.save(FILE_NAME);
+                let expires_at = match until.map(|date| end_of_day(date)).transpose() {
+                    Ok(at) => at,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
                     }
+                };
+                let authorized = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| BalanceManager::authorize(storage, &name, amount, expires_at),
+                    |storage, id| JournalRecord::Authorize(storage.get_hold(*id).unwrap().clone()),
+                );
+                match authorized {
+                    Ok(id) => println!(
+                        "Заблокировано {} на счёте {}, блокировка #{}",
+                        amount, name, id
+                    ),
                     Err(e) => println!("Ошибка: {}", e),
                 }
             }
-            "balance" => {
+            "capture" => {
+                let Some(Ok(id)) = args.get(1).map(|id| id.parse::<HoldId>()) else {
+                    println!("Пример: capture 3, capture 3 40 или capture 3 --to Bob");
+                    continue;
+                };
+                let (amount, to) = match args.iter().position(|a| *a == "--to") {
+                    Some(pos) => (&args[2.min(pos)..pos], args.get(pos + 1)),
+                    None => (&args[2..], None),
+                };
+                let Some(hold) = storage.get_hold(id) else {
+                    println!("Ошибка: {}", BankError::HoldNotFound(id));
+                    continue;
+                };
+                let amount = if amount.is_empty() {
+                    hold.amount
+                } else {
+                    match parse_amount(amount) {
+                        Ok(a) => a,
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    }
+                };
+                let tx = Capture {
+                    hold: id,
+                    account: hold.account.clone(),
+                    amount,
+                    to: to.map(|to| to.to_string()),
+                };
+                if execute(&mut storage, &mut persistence, &tx, memo) {
+                    println!(
+                        "По блокировке #{} списано {} со счёта {}",
+                        id, amount, tx.account
+                    );
+                }
+            }
+            "release" => {
+                let Some(Ok(id)) = args.get(1).map(|id| id.parse::<HoldId>()) else {
+                    println!("Пример: release 3");
+                    continue;
+                };
+                let released = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| BalanceManager::release(storage, id),
+                    |_, _| JournalRecord::Release { hold: id },
+                );
+                match released {
+                    Ok(hold) => println!("Блокировка #{} на {} снята", id, hold.amount),
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "holds" => {
                 if args.len() != 2 {
-                    println!("Пример: balance John");
+                    println!("Пример: holds John");
+                    continue;
+                }
+                let name = args[1].to_string();
+                let Some(balance) = BalanceManager::get_balance(&storage, &name) else {
+                    println!("Пользователь {} не найден", name);
+                    continue;
+                };
+                for hold in BalanceManager::holds(&storage, &name) {
+                    let expires = match hold.expires_at {
+                        Some(at) => format!(" до {}", at),
+                        None => String::new(),
+                    };
+                    println!(
+                        "  #{} {} от {}{}",
+                        hold.id, hold.amount, hold.placed_at, expires
+                    );
+                }
+                println!("Баланс: {}", balance);
+                if let Some(available) = BalanceManager::get_available_balance(&storage, &name) {
+                    println!("Доступно: {}", available);
+                }
+            }
+            "fee" | "interest" => {
+                if !(3..=4).contains(&args.len()) {
+                    println!("Пример: {} John 10", args[0]);
                     continue;
                 }
                 let name = args[1].to_string();
+                if is_internal(&name) {
+                    println!("Ошибка: {}", BankError::ReservedAccount(name));
+                    continue;
+                }
+                let amount = match parse_amount(&args[2..]) {
+                    Ok(a) => a,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                let (tx, done) = if args[0] == "fee" {
+                    (Booking::fee(name.clone(), amount), "списана комиссия")
+                } else {
+                    (
+                        Booking::interest(name.clone(), amount),
+                        "начислены проценты",
+                    )
+                };
+                if execute(&mut storage, &mut persistence, &tx, memo) {
+                    println!("Пользователю {} {} {}", name, done, amount);
+                }
+            }
+            "balance" => {
+                let name = match args[1..] {
+                    [name] => name.to_string(),
+                    [name, "--at", date] => {
+                        let at = match end_of_day(date) {
+                            Ok(at) => at,
+                            Err(e) => {
+                                println!("{}", e);
+                                continue;
+                            }
+                        };
+                        match BalanceManager::get_balance_at(&storage, &name.to_string(), at) {
+                            Some(b) => println!("Баланс пользователя {} на {}: {}", name, at, b),
+                            None => println!("Пользователя {} на {} не было", name, at),
+                        }
+                        continue;
+                    }
+                    _ => {
+                        println!("Пример: balance John или balance John --at 2024-05-31");
+                        continue;
+                    }
+                };
                 match BalanceManager::get_balance(&storage, &name) {
-                    Some(b) => println!("Баланс пользователя {}: {}", name, b),
+                    Some(b) => {
+                        println!("Баланс пользователя {}: {}", name, b);
+                        let available = BalanceManager::get_available_balance(&storage, &name);
+                        if let Some(available) = available.filter(|a| *a != b) {
+                            println!("Доступно с учётом блокировок: {}", available);
+                        }
+                    }
                     None => println!("Пользователь {} не найден", name),
                 }
             }
             "transfer" => {
-                if args.len() != 4 {
-                    println!("Пример: tx_transfer Alice Bob 50");
+                if !(4..=5).contains(&args.len()) {
+                    println!("Пример: transfer Alice Bob 50");
                     continue;
                 }
                 let from = args[1].to_string();
                 let to = args[2].to_string();
-                let amount: i64 = match args[3].parse() {
+                let amount = match parse_amount(&args[3..]) {
                     Ok(a) => a,
-                    Err(_) => {
-                        println!("Сумма должна быть числом");
+                    Err(e) => {
+                        println!("{}", e);
                         continue;
                     }
                 };
@@ -163,12 +640,119 @@ fn main() {
                     to: to.clone(),
                     amount,
                 };
-                match tx.apply(&mut storage) {
-                    Ok(_) => {
-                        println!("Транзакция: перевод {} на {}", from, to);
-                        storage.save(FILE_NAME);
+                if execute(&mut storage, &mut persistence, &tx, memo) {
+                    println!("Транзакция: перевод {} на {}", from, to);
+                }
+            }
+            "exchange" => {
+                if !(4..=5).contains(&args.len()) {
+                    println!("Пример: exchange Alice alice-eur 1000");
+                    continue;
+                }
+                let from = args[1].to_string();
+                let to = args[2].to_string();
+                let amount = match parse_amount(&args[3..]) {
+                    Ok(a) => a,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
                     }
-                    Err(e) => println!("Ошибка транзакции: {:?}", e),
+                };
+                let Some(account) = UserManager::get_account(&storage, &to) else {
+                    println!("Пользователь {} не найден", to);
+                    continue;
+                };
+                // Файл читается заново, чтобы новые курсы действовали без перезапуска
+                let tx = RateTable::load(RATES_NAME)
+                    .and_then(|rates| rates.get(amount.currency(), account.currency()))
+                    .and_then(|rate| Exchange::new(from.clone(), to.clone(), amount, rate));
+                let tx = match tx {
+                    Ok(tx) => tx,
+                    Err(e) => {
+                        println!("Ошибка: {}", e);
+                        continue;
+                    }
+                };
+                if execute(&mut storage, &mut persistence, &tx, memo) {
+                    println!(
+                        "Обмен: {} со счёта {} -> {} на {} по курсу {}",
+                        amount, from, tx.converted, to, tx.rate
+                    );
+                }
+            }
+            "reverse" => {
+                if args.len() != 2 {
+                    println!("Пример: reverse 12");
+                    continue;
+                }
+                let Ok(id) = args[1].parse::<TxId>() else {
+                    println!("Некорректный номер транзакции '{}'", args[1]);
+                    continue;
+                };
+                let reversed = apply(
+                    &mut storage,
+                    &mut persistence,
+                    |storage| storage.reverse(id, memo),
+                    |_, receipt| JournalRecord::Posted {
+                        receipt: receipt.clone(),
+                        record: Box::new(JournalRecord::Reverse { id }),
+                    },
+                );
+                match reversed {
+                    Ok(receipt) => println!("Транзакция #{} отменена, {}", id, receipt),
+                    Err(e) => println!("Ошибка отмены: {}", e),
+                }
+            }
+            "statement" => {
+                if !(2..=4).contains(&args.len()) {
+                    println!("Пример: statement Alice 2024-05-01 2024-05-31");
+                    continue;
+                }
+                let (from, to) = match parse_period(&args[2..]) {
+                    Ok(period) => period,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                match storage.statement(&args[1].to_string(), from, to) {
+                    Ok(statement) => print_statement(&statement),
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "trial" => {
+                if args.len() != 1 {
+                    println!("Пример: trial");
+                    continue;
+                }
+                match storage.trial_balance() {
+                    Ok(trial) => print_trial_balance(&trial),
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "report" => {
+                if args.len() != 1 {
+                    println!("Пример: report");
+                    continue;
+                }
+                match storage.balances_by_type() {
+                    Ok(groups) => print_groups(&groups),
+                    Err(e) => println!("Ошибка: {}", e),
+                }
+            }
+            "compact" => {
+                if args.len() != 1 {
+                    println!("Пример: compact");
+                    continue;
+                }
+                match &mut persistence {
+                    Persistence::Journal(journal) => match journal.compact(&mut storage, FILE_NAME)
+                    {
+                        Ok(_) => println!("Журнал перенесён в {}", FILE_NAME),
+                        Err(e) => println!("Ошибка сжатия журнала: {}", e),
+                    },
+                    #[cfg(feature = "sqlite")]
+                    Persistence::Database => println!("База данных не ведёт журнал"),
                 }
             }
             "exit" => break,
@@ -178,3 +762,325 @@ fn main() {
 
     println!("Выход из CLI, все изменения сохранены.");
 }
+
+/// Применяет транзакцию, записывает её в журнал и печатает квитанцию;
+/// возвращает true при успехе
+fn execute(
+    storage: &mut Storage,
+    persistence: &mut Persistence,
+    tx: &dyn Transaction,
+    memo: Option<&str>,
+) -> bool {
+    let executed = apply(
+        storage,
+        persistence,
+        |storage| storage.execute(tx, memo),
+        |_, receipt| posted(receipt.clone(), tx),
+    );
+    match executed {
+        Ok(receipt) => {
+            println!("Применена {}", receipt);
+            true
+        }
+        Err(e) => {
+            println!("Ошибка транзакции: {}", e);
+            false
+        }
+    }
+}
+
+/// Запись журнала вместе с квитанцией, чтобы номер, время и комментарий
+/// сохранились при воспроизведении
+fn posted(receipt: Receipt, tx: &dyn Transaction) -> JournalRecord {
+    JournalRecord::Posted {
+        receipt,
+        record: Box::new(tx.journal_record()),
+    }
+}
+
+/// Запись об открытии счёта вместе с временем открытия, чтобы при
+/// воспроизведении счёт и его первые проводки не получили новую дату
+fn stamped(storage: &Storage, record: &JournalRecord) -> JournalRecord {
+    let mut record = record.clone();
+    if let JournalRecord::AddUser {
+        name, opened_at, ..
+    }
+    | JournalRecord::OpenAccount {
+        name, opened_at, ..
+    } = &mut record
+    {
+        *opened_at = UserManager::get_account(storage, name).map(|a| a.opened_at);
+    }
+    record
+}
+
+/// Лимиты счёта после изменения, для записи в журнал
+fn set_limits(storage: &Storage, name: &Name) -> JournalRecord {
+    JournalRecord::SetLimits {
+        name: name.clone(),
+        limits: UserManager::get_account(storage, name).unwrap().limits,
+    }
+}
+
+/// Вносит изменение и записывает его в журнал или базу. Об успехе можно
+/// сообщать только после записи: если она не удалась, изменение отменяется.
+fn apply<T>(
+    storage: &mut Storage,
+    persistence: &mut Persistence,
+    change: impl FnOnce(&mut Storage) -> Result<T, BankError>,
+    record: impl FnOnce(&Storage, &T) -> JournalRecord,
+) -> Result<T, BankError> {
+    storage.apply_durably(change, |storage, value| {
+        let record = record(storage, value);
+        persist(persistence, storage, &record)
+    })
+}
+
+/// Записывает применённое изменение в журнал или фиксирует его в базе
+fn persist(
+    persistence: &mut Persistence,
+    storage: &mut Storage,
+    record: &JournalRecord,
+) -> Result<(), BankError> {
+    // Без базы данных хранилище фиксируется только при сжатии журнала
+    #[cfg(not(feature = "sqlite"))]
+    let _ = storage;
+    match persistence {
+        Persistence::Journal(journal) => journal.append(record),
+        #[cfg(feature = "sqlite")]
+        Persistence::Database => storage.record(record).and_then(|_| storage.commit()),
+    }
+}
+
+/// Открывает хранилище: CSV-снимок с журналом или базу SQLite (`--db <file>`)
+fn open_storage(args: &[String]) -> (Storage, Persistence) {
+    if let Some(pos) = args.iter().position(|a| a == "--db") {
+        let Some(db) = args.get(pos + 1) else {
+            eprintln!("Пример: --db bank.db");
+            process::exit(1);
+        };
+        return open_database(db);
+    }
+
+    // С флагом --strict отказываемся работать с повреждённым файлом
+    let mode = if args.iter().any(|a| a == "--strict") {
+        LoadMode::Strict
+    } else {
+        LoadMode::Lenient
+    };
+    let mut storage = match Storage::try_load_data(FILE_NAME, mode) {
+        Ok(report) => {
+            for e in &report.skipped {
+                eprintln!("Пропущена {} файла {}", e, FILE_NAME);
+            }
+            if report.source == LoadSource::Backup {
+                eprintln!(
+                    "{} не найден, данные восстановлены из резервной копии",
+                    FILE_NAME
+                );
+            }
+            report.storage
+        }
+        Err(e) => {
+            eprintln!("Не удалось загрузить {}: {}", FILE_NAME, e);
+            process::exit(1);
+        }
+    };
+
+    // Воспроизводим журнал изменений поверх снимка
+    match Journal::open(JOURNAL_NAME, &mut storage) {
+        Ok(journal) => (storage, Persistence::Journal(journal)),
+        Err(e) => {
+            eprintln!("Не удалось воспроизвести журнал {}: {}", JOURNAL_NAME, e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open_database(db: &str) -> (Storage, Persistence) {
+    // При первом запуске переносим счета из CSV-файла в базу
+    let opened = SqliteBackend::open_or_migrate(db, FILE_NAME, JOURNAL_NAME).and_then(|backend| {
+        // Номера транзакций и проводки восстанавливаются из их истории в базе
+        let history = TxLog::rebuild(backend.transactions()?);
+        let postings = backend.postings()?;
+        let mut storage = Storage::with_backend(backend);
+        storage.restore_history(history);
+        storage.restore_postings(postings);
+        Ok(storage)
+    });
+    match opened {
+        Ok(storage) => (storage, Persistence::Database),
+        Err(e) => {
+            eprintln!("Не удалось открыть базу {}: {}", db, e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_database(_db: &str) -> (Storage, Persistence) {
+    eprintln!("Программа собрана без поддержки SQLite (feature \"sqlite\")");
+    process::exit(1);
+}
+
+/// Разбирает период выписки: обе даты включительно, любую можно опустить
+fn parse_period(args: &[&str]) -> Result<(Option<Timestamp>, Option<Timestamp>), BankError> {
+    let from = args.first().map(|s| s.parse::<Timestamp>()).transpose()?;
+    // Конечная дата входит в период целиком
+    let to = args
+        .get(1)
+        .map(|s| end_of_day(s))
+        .transpose()?
+        .map(|to| Timestamp::from_unix(to.unix() + 1));
+    Ok((from, to))
+}
+
+/// Последняя секунда дня: "2024-05-31" означает состояние на конец 31 мая
+fn end_of_day(date: &str) -> Result<Timestamp, BankError> {
+    let at = date.parse::<Timestamp>()?;
+    if date.contains(':') {
+        Ok(at)
+    } else {
+        Ok(Timestamp::from_unix(at.unix() + 24 * 60 * 60 - 1))
+    }
+}
+
+fn print_statement(statement: &Statement) {
+    println!("Выписка по счёту {}", statement.account);
+    println!("Входящий остаток: {}", statement.opening);
+    for line in &statement.lines {
+        let posting = &line.posting;
+        let tx = posting
+            .tx
+            .map(|id| format!("#{}", id))
+            .unwrap_or_else(|| "-".to_string());
+        let description = match posting.kind {
+            PostingKind::Open => "открытие счёта".to_string(),
+            PostingKind::Close => "закрытие счёта".to_string(),
+            PostingKind::Movement => posting.memo.clone().unwrap_or_default(),
+        };
+        let sign = if posting.amount.is_negative() {
+            ""
+        } else {
+            "+"
+        };
+        let row = format!(
+            "  {}  {:>6}  {}{}  остаток {}  {}",
+            posting.at, tx, sign, posting.amount, line.balance, description
+        );
+        println!("{}", row.trim_end());
+    }
+    println!("Исходящий остаток: {}", statement.closing);
+}
+
+/// Разбирает "<tx|day> <amount>"; нулевая сумма снимает лимит
+fn parse_limit(args: &[&str]) -> Option<(LimitKind, Option<Money>)> {
+    let kind = match *args.first()? {
+        "tx" => LimitKind::PerTransaction,
+        "day" => LimitKind::Daily,
+        _ => return None,
+    };
+    if !(2..=3).contains(&args.len()) {
+        return None;
+    }
+    let limit: Money = args[1..].join(" ").parse().ok()?;
+    Some((kind, Some(limit).filter(|l| !l.is_zero())))
+}
+
+fn print_limit(kind: LimitKind, owner: &str, limit: Option<Money>) {
+    let label = match kind {
+        LimitKind::PerTransaction => "Лимит операции",
+        LimitKind::Daily => "Дневной лимит",
+    };
+    match limit {
+        Some(limit) => println!("{} {}: {}", label, owner, limit),
+        None => println!("{} {} снят", label, owner),
+    }
+}
+
+fn status_label(status: AccountStatus) -> &'static str {
+    match status {
+        AccountStatus::Active => "активен",
+        AccountStatus::Frozen => "заморожен",
+        AccountStatus::Dormant => "неактивен",
+        AccountStatus::Closed => "закрыт",
+    }
+}
+
+/// Пометка для списков счетов; у активного счёта её нет
+fn status_mark(status: AccountStatus) -> String {
+    match status {
+        AccountStatus::Active => String::new(),
+        status => format!(" [{}]", status_label(status)),
+    }
+}
+
+fn print_account(storage: &Storage, account: &Account) {
+    println!("Счёт {} (№{})", account.name, account.id);
+    for id in &account.owners {
+        match UserManager::get_customer(storage, *id) {
+            Some(customer) => println!("  Владелец: {} (клиент #{})", customer.name, id),
+            None => println!("  Владелец: клиент #{}", id),
+        }
+    }
+    // Счета из старых файлов не знают даты открытия
+    if account.opened_at == Timestamp::default() {
+        println!("  Открыт: неизвестно");
+    } else {
+        println!("  Открыт: {}", account.opened_at);
+    }
+    println!("  Статус: {}", status_label(account.status));
+    println!("  Баланс: {}", account.balance);
+    let limits = [
+        ("Овердрафт", account.limits.overdraft),
+        ("Лимит операции", account.limits.per_transaction),
+        ("Дневной лимит", account.limits.daily),
+    ];
+    for (label, limit) in limits {
+        if let Some(limit) = limit {
+            println!("  {}: {}", label, limit);
+        }
+    }
+}
+
+fn print_trial_balance(trial: &TrialBalance) {
+    println!("{:<20} {:>20} {:>20}", "Счёт", "Дебет", "Кредит");
+    for row in &trial.rows {
+        let (debit, credit) = match row.side {
+            Side::Debit => (row.amount.to_string(), String::new()),
+            Side::Credit => (String::new(), row.amount.to_string()),
+        };
+        println!("{:<20} {:>20} {:>20}", row.account, debit, credit);
+    }
+    for (debits, credits) in trial.totals.values() {
+        println!(
+            "{:<20} {:>20} {:>20}",
+            "Итого",
+            debits.to_string(),
+            credits.to_string()
+        );
+    }
+    if trial.is_balanced() {
+        println!("Дебет и кредит сходятся");
+    } else {
+        println!("ВНИМАНИЕ: дебет и кредит не сходятся");
+    }
+}
+
+fn print_groups(groups: &[AccountGroup]) {
+    for group in groups {
+        println!("{}:", group.kind);
+        for (name, balance) in &group.accounts {
+            println!("  {:<20} {:>20}", name, balance.to_string());
+        }
+        for total in group.totals.values() {
+            println!("  {:<20} {:>20}", "Итого", total.to_string());
+        }
+    }
+}
+
+/// Разбирает сумму операции ("100", "12.50" или "12.50 EUR"); сумма должна быть больше нуля
+fn parse_amount(args: &[&str]) -> Result<Money, BankError> {
+    args.join(" ").parse::<Money>()?.ensure_positive()
+}
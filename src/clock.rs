@@ -0,0 +1,185 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::BankError;
+
+pub(crate) const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A moment in time, in whole seconds since 1970-01-01 00:00:00 UTC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(i64);
+
+impl Timestamp {
+    pub fn from_unix(seconds: i64) -> Timestamp {
+        Timestamp(seconds)
+    }
+
+    pub fn unix(&self) -> i64 {
+        self.0
+    }
+
+    /// Midnight UTC of the given calendar date
+    pub fn from_date(year: i64, month: u32, day: u32) -> Result<Timestamp, BankError> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err(BankError::Parse(format!(
+                "некорректная дата {:04}-{:02}-{:02}",
+                year, month, day
+            )));
+        }
+        Ok(Timestamp(
+            days_from_civil(year, month, day) * SECONDS_PER_DAY,
+        ))
+    }
+
+    /// Calendar date (year, month, day) in UTC
+    pub fn date(&self) -> (i64, u32, u32) {
+        civil_from_days(self.0.div_euclid(SECONDS_PER_DAY))
+    }
+}
+
+/// Formats as "2024-05-01 09:30:00" (UTC)
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.date();
+        let seconds = self.0.rem_euclid(SECONDS_PER_DAY);
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// Parses "2024-05-01" (midnight) or "2024-05-01 09:30:00", both in UTC
+impl FromStr for Timestamp {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BankError::Parse(format!("'{}' не является датой", s));
+        let number = |part: &str| part.parse::<u32>().map_err(|_| invalid());
+
+        let (date, time) = match s.trim().split_once([' ', 'T']) {
+            Some((date, time)) => (date, Some(time)),
+            None => (s.trim(), None),
+        };
+        let [year, month, day] = date.split('-').collect::<Vec<_>>()[..] else {
+            return Err(invalid());
+        };
+        let year = year.parse::<i64>().map_err(|_| invalid())?;
+        let midnight = Timestamp::from_date(year, number(month)?, number(day)?)?;
+
+        let Some(time) = time else {
+            return Ok(midnight);
+        };
+        let [hours, minutes, seconds] = time.split(':').collect::<Vec<_>>()[..] else {
+            return Err(invalid());
+        };
+        let (hours, minutes, seconds) = (number(hours)?, number(minutes)?, number(seconds)?);
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return Err(invalid());
+        }
+        Ok(Timestamp(
+            midnight.0 + i64::from(hours * 3600 + minutes * 60 + seconds),
+        ))
+    }
+}
+
+/// Source of the current time; tests substitute a fixed clock
+pub trait Clock {
+    fn now(&self) -> Timestamp;
+}
+
+/// The operating system clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        Timestamp(seconds)
+    }
+}
+
+/// A clock that always reports the same moment
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub Timestamp);
+
+impl Clock for FixedClock {
+    fn now(&self) -> Timestamp {
+        self.0
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Преобразования между датой и числом дней от 1970-01-01 по алгоритму
+// Говарда Хиннанта (пролептический григорианский календарь)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_and_parse() {
+        let ts: Timestamp = "2024-02-29 23:59:01".parse().unwrap();
+        assert_eq!(ts.unix(), 1_709_251_141);
+        assert_eq!(ts.to_string(), "2024-02-29 23:59:01");
+        assert_eq!(ts.date(), (2024, 2, 29));
+
+        assert_eq!(
+            "1970-01-01".parse::<Timestamp>().unwrap(),
+            Timestamp::default()
+        );
+        assert_eq!(Timestamp::from_unix(-1).to_string(), "1969-12-31 23:59:59");
+
+        assert!("2023-02-29".parse::<Timestamp>().is_err());
+        assert!("2024-05-01 24:00:00".parse::<Timestamp>().is_err());
+        assert!("01.05.2024".parse::<Timestamp>().is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use std::{fmt, str::FromStr};
+
+use crate::{clock::Timestamp, error::BankError};
+
+/// Stable identifier of a customer
+pub type CustomerId = u64;
+
+/// A person or company holding one or more accounts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Customer {
+    pub id: CustomerId,
+    pub name: String,
+    pub since: Timestamp,
+}
+
+impl Customer {
+    /// Customer names are stored in CSV rows and journal lines
+    pub(crate) fn validate_name(name: &str) -> Result<(), BankError> {
+        if name.trim().is_empty() || name.contains([',', '\n', '\r']) {
+            return Err(BankError::Parse(format!(
+                "некорректное имя клиента '{}'",
+                name
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Stored as `id,since,name`
+impl fmt::Display for Customer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.id, self.since.unix(), self.name)
+    }
+}
+
+impl FromStr for Customer {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BankError::Parse(format!("некорректный клиент '{}'", s));
+        let [id, since, name] = s.split(',').collect::<Vec<_>>()[..] else {
+            return Err(invalid());
+        };
+        Customer::validate_name(name)?;
+        Ok(Customer {
+            id: id.parse().map_err(|_| invalid())?,
+            since: Timestamp::from_unix(since.parse().map_err(|_| invalid())?),
+            name: name.to_string(),
+        })
+    }
+}
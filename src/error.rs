@@ -0,0 +1,237 @@
+use std::{error::Error, fmt, io};
+
+use crate::{
+    account::{AccountStatus, LimitKind},
+    customer::CustomerId,
+    history::TxId,
+    hold::HoldId,
+    money::{Currency, Money},
+    storage::Name,
+};
+
+/// Errors produced by `Storage`, the managers and every `Transaction` impl
+#[derive(Debug)]
+pub enum BankError {
+    /// No account with the given name exists
+    AccountNotFound(Name),
+    /// The account balance does not cover the requested amount
+    InsufficientFunds(Name),
+    /// The amount cannot be used for the requested operation
+    InvalidAmount(String),
+    /// An account with the given name already exists
+    DuplicateAccount(Name),
+    /// The operation would overflow the balance
+    Overflow,
+    /// Reading or writing persisted data failed
+    Io(io::Error),
+    /// Persisted or user-supplied data could not be parsed
+    Parse(String),
+    /// A data file contains malformed rows and was refused in strict mode
+    CorruptedData(Vec<LineError>),
+    /// The database backend reported an error
+    Database(String),
+    /// Amounts in different currencies were combined
+    CurrencyMismatch { expected: Currency, found: Currency },
+    /// Deposits, withdrawals and transfers require an amount greater than zero
+    NonPositiveAmount(Money),
+    /// A transaction refers to an account that does not exist
+    InvalidAccount { side: AccountSide, name: Name },
+    /// A step of a batch failed and the whole batch was rolled back
+    BatchFailed {
+        /// 1-based number of the failed step
+        step: usize,
+        source: Box<BankError>,
+    },
+    /// No applied transaction with the given ID is known
+    TransactionNotFound(TxId),
+    /// The transaction has already been compensated by another one
+    AlreadyReversed { id: TxId, by: TxId },
+    /// The idempotency key was already used for a different transaction
+    IdempotencyConflict { key: String, original: TxId },
+    /// The name belongs to the bank's own accounts and cannot be used by customers
+    ReservedAccount(Name),
+    /// No customer with the given ID exists
+    CustomerNotFound(CustomerId),
+    /// An account must be held by at least one customer
+    MissingOwner(Name),
+    /// The account's status does not allow the operation
+    AccountUnavailable { name: Name, status: AccountStatus },
+    /// An account with money left on it can only be closed with a payout account
+    AccountNotEmpty(Name),
+    /// The customer owes money on the account, so it cannot be closed or removed
+    AccountOverdrawn(Name),
+    /// Active holds reserve money on the account, so it cannot be closed or removed
+    AccountHasHolds(Name),
+    /// The debit would take the balance below the account's overdraft limit
+    OverdraftExceeded { name: Name, limit: Money },
+    /// The debit is larger than a spending limit of the account allows
+    SpendingLimitExceeded {
+        name: Name,
+        kind: LimitKind,
+        limit: Money,
+        /// How much may still leave the account under this limit
+        remaining: Money,
+    },
+    /// No hold with the given ID exists; it may have been captured or released
+    HoldNotFound(HoldId),
+    /// The hold expired and can no longer be captured
+    HoldExpired(HoldId),
+    /// The rate table has no rate for exchanging `from` into `to`
+    RateNotFound { from: Currency, to: Currency },
+}
+
+/// Which account of a transaction an error refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountSide {
+    /// The account money is taken from
+    Source,
+    /// The account money is credited to
+    Destination,
+}
+
+/// A malformed row found while loading a data file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineError {
+    /// 1-based line number in the file
+    pub line: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "строка {}: {}", self.line, self.reason)
+    }
+}
+
+impl fmt::Display for BankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BankError::AccountNotFound(name) => write!(f, "Пользователь {} не найден", name),
+            BankError::InsufficientFunds(name) => {
+                write!(f, "Недостаточно средств на счёте {}", name)
+            }
+            BankError::InvalidAmount(reason) => write!(f, "Некорректная сумма: {}", reason),
+            BankError::DuplicateAccount(name) => write!(f, "Пользователь {} уже существует", name),
+            BankError::Overflow => write!(f, "Переполнение баланса"),
+            BankError::Io(e) => write!(f, "Ошибка ввода-вывода: {}", e),
+            BankError::Parse(reason) => write!(f, "Ошибка разбора данных: {}", reason),
+            BankError::CorruptedData(errors) => {
+                write!(f, "Файл повреждён, некорректных строк: {}", errors.len())?;
+                for e in errors {
+                    write!(f, "; {}", e)?;
+                }
+                Ok(())
+            }
+            BankError::Database(reason) => write!(f, "Ошибка базы данных: {}", reason),
+            BankError::CurrencyMismatch { expected, found } => {
+                write!(f, "Ожидалась валюта {}, получена {}", expected, found)
+            }
+            BankError::NonPositiveAmount(amount) => {
+                write!(f, "Сумма должна быть больше нуля, получено {}", amount)
+            }
+            BankError::InvalidAccount { side, name } => match side {
+                AccountSide::Source => write!(f, "Счёт отправителя {} не найден", name),
+                AccountSide::Destination => write!(f, "Счёт получателя {} не найден", name),
+            },
+            BankError::BatchFailed { step, source } => {
+                write!(f, "Пакет отменён, ошибка на шаге {}: {}", step, source)
+            }
+            BankError::TransactionNotFound(id) => write!(f, "Транзакция #{} не найдена", id),
+            BankError::AlreadyReversed { id, by } => {
+                write!(f, "Транзакция #{} уже отменена транзакцией #{}", id, by)
+            }
+            BankError::IdempotencyConflict { key, original } => write!(
+                f,
+                "Ключ '{}' уже использован транзакцией #{} с другими параметрами",
+                key, original
+            ),
+            BankError::ReservedAccount(name) => {
+                write!(f, "Счёт {} принадлежит банку", name)
+            }
+            BankError::CustomerNotFound(id) => write!(f, "Клиент #{} не найден", id),
+            BankError::MissingOwner(name) => {
+                write!(f, "У счёта {} должен быть хотя бы один владелец", name)
+            }
+            BankError::AccountUnavailable { name, status } => match status {
+                AccountStatus::Active => write!(f, "Операция по счёту {} недоступна", name),
+                AccountStatus::Frozen => write!(f, "Счёт {} заморожен", name),
+                AccountStatus::Dormant => write!(f, "Счёт {} неактивен", name),
+                AccountStatus::Closed => write!(f, "Счёт {} закрыт", name),
+            },
+            BankError::AccountNotEmpty(name) => write!(
+                f,
+                "На счёте {} остались средства, укажите счёт для выплаты остатка",
+                name
+            ),
+            BankError::AccountOverdrawn(name) => {
+                write!(f, "Счёт {} в минусе, сначала погасите задолженность", name)
+            }
+            BankError::AccountHasHolds(name) => write!(
+                f,
+                "На счёте {} есть действующие блокировки, спишите или снимите их",
+                name
+            ),
+            BankError::OverdraftExceeded { name, limit } => {
+                write!(f, "Превышен лимит овердрафта {} по счёту {}", limit, name)
+            }
+            BankError::SpendingLimitExceeded {
+                name,
+                kind,
+                limit,
+                remaining,
+            } => {
+                let kind = match kind {
+                    LimitKind::PerTransaction => "лимит операции",
+                    LimitKind::Daily => "дневной лимит",
+                };
+                write!(
+                    f,
+                    "Превышен {} {} по счёту {}, доступно ещё {}",
+                    kind, limit, name, remaining
+                )
+            }
+            BankError::HoldNotFound(id) => write!(f, "Блокировка #{} не найдена", id),
+            BankError::HoldExpired(id) => write!(f, "Срок блокировки #{} истёк", id),
+            BankError::RateNotFound { from, to } => {
+                write!(f, "Нет курса обмена {} на {}", from, to)
+            }
+        }
+    }
+}
+
+impl Error for BankError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BankError::Io(e) => Some(e),
+            BankError::BatchFailed { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BankError {
+    fn from(e: io::Error) -> Self {
+        BankError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            BankError::AccountNotFound("Alice".to_string()).to_string(),
+            "Пользователь Alice не найден"
+        );
+        assert_eq!(BankError::Overflow.to_string(), "Переполнение баланса");
+    }
+
+    #[test]
+    fn test_io_source() {
+        let err: BankError = io::Error::new(io::ErrorKind::NotFound, "missing").into();
+        assert!(matches!(err, BankError::Io(_)));
+        assert!(err.source().is_some());
+    }
+}
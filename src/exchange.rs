@@ -0,0 +1,248 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path, str::FromStr};
+
+use crate::{
+    error::{BankError, LineError},
+    money::{Currency, Money},
+};
+
+/// Most digits a rate may have after the decimal point
+pub const MAX_RATE_DIGITS: u32 = 9;
+
+/// How many units of `quote` the bank pays for one unit of `base`,
+/// e.g. `EUR/RUB 98.50`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    pub base: Currency,
+    pub quote: Currency,
+    /// The rate is `units / 10^digits`, kept exactly as it was written
+    units: u64,
+    digits: u32,
+}
+
+impl Rate {
+    /// Converts an amount in `base` into `quote`. Fractions of the smallest
+    /// unit of `quote` are dropped, so the customer never gets more than
+    /// the rate allows.
+    pub fn convert(&self, amount: Money) -> Result<Money, BankError> {
+        if amount.currency() != self.base {
+            return Err(BankError::CurrencyMismatch {
+                expected: self.base,
+                found: amount.currency(),
+            });
+        }
+        let pow = |digits: u32| 10_i128.pow(digits);
+        let minor = i128::from(amount.minor_units())
+            .checked_mul(i128::from(self.units))
+            .and_then(|m| m.checked_mul(pow(self.quote.minor_digits())))
+            .ok_or(BankError::Overflow)?
+            / (pow(self.digits) * pow(self.base.minor_digits()));
+        let minor = i64::try_from(minor).map_err(|_| BankError::Overflow)?;
+        Ok(Money::new(minor, self.quote))
+    }
+
+    /// Whether the rate converts between these two currencies, in either direction
+    pub fn connects(&self, a: Currency, b: Currency) -> bool {
+        (self.base, self.quote) == (a, b) || (self.base, self.quote) == (b, a)
+    }
+}
+
+/// Written as `BASE/QUOTE value`
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{} ", self.base, self.quote)?;
+        match self.digits {
+            0 => write!(f, "{}", self.units),
+            digits => {
+                let per_unit = 10_u64.pow(digits);
+                write!(
+                    f,
+                    "{}.{:0width$}",
+                    self.units / per_unit,
+                    self.units % per_unit,
+                    width = digits as usize
+                )
+            }
+        }
+    }
+}
+
+impl FromStr for Rate {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BankError::Parse(format!("некорректный курс '{}'", s));
+        let [pair, value] = s.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err(invalid());
+        };
+        let (base, quote) = pair.split_once('/').ok_or_else(invalid)?;
+        let (base, quote): (Currency, Currency) = (base.parse()?, quote.parse()?);
+        if base == quote {
+            return Err(invalid());
+        }
+
+        let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+        if whole.is_empty()
+            || !whole.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+            || (value.contains('.') && fraction.is_empty())
+            || fraction.len() > MAX_RATE_DIGITS as usize
+        {
+            return Err(invalid());
+        }
+        let units: u64 = format!("{}{}", whole, fraction)
+            .parse()
+            .map_err(|_| invalid())?;
+        if units == 0 {
+            return Err(invalid());
+        }
+        Ok(Rate {
+            base,
+            quote,
+            units,
+            digits: fraction.len() as u32,
+        })
+    }
+}
+
+/// Exchange rates by currency pair.
+///
+/// The bank buys and sells at different rates, so each direction is listed
+/// on its own: `EUR/RUB` does not give the rate for `RUB/EUR`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RateTable {
+    rates: BTreeMap<(Currency, Currency), Rate>,
+}
+
+impl RateTable {
+    pub fn new() -> RateTable {
+        RateTable::default()
+    }
+
+    /// Reads a rate file: one `BASE/QUOTE value` rate per line; blank lines
+    /// and lines starting with '#' are skipped
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<RateTable, BankError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Adds the rate, replacing an earlier one for the same pair
+    pub fn insert(&mut self, rate: Rate) {
+        self.rates.insert((rate.base, rate.quote), rate);
+    }
+
+    /// The rate at which `from` is exchanged into `to`
+    pub fn get(&self, from: Currency, to: Currency) -> Result<Rate, BankError> {
+        self.rates
+            .get(&(from, to))
+            .copied()
+            .ok_or(BankError::RateNotFound { from, to })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rate> {
+        self.rates.values()
+    }
+}
+
+impl FromStr for RateTable {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut table = RateTable::new();
+        let mut errors = Vec::new();
+        for (index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |reason: String| LineError {
+                line: index + 1,
+                reason,
+            };
+            match line.parse::<Rate>() {
+                Ok(rate) if table.get(rate.base, rate.quote).is_ok() => errors.push(error(
+                    format!("курс {}/{} указан дважды", rate.base, rate.quote),
+                )),
+                Ok(rate) => table.insert(rate),
+                Err(e) => errors.push(error(e.to_string())),
+            }
+        }
+        // Обмен по неверному курсу не исправить, поэтому файл с ошибками не принимается
+        if errors.is_empty() {
+            Ok(table)
+        } else {
+            Err(BankError::CorruptedData(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::money::rub;
+
+    #[test]
+    fn test_rate_converts_between_minor_units() {
+        let rate: Rate = "EUR/RUB 98.50".parse().unwrap();
+        assert_eq!(rate.to_string(), "EUR/RUB 98.50");
+        assert_eq!(
+            rate.convert(Money::from_major(100, Currency::EUR).unwrap())
+                .unwrap(),
+            rub(9850)
+        );
+        // Доли копейки отбрасываются
+        assert_eq!(
+            rate.convert(Money::new(1, Currency::EUR)).unwrap(),
+            Money::new(98, Currency::RUB)
+        );
+        assert!(matches!(
+            rate.convert(rub(1)),
+            Err(BankError::CurrencyMismatch { .. })
+        ));
+
+        let yen: Rate = "JPY/RUB 0.61".parse().unwrap();
+        assert_eq!(
+            yen.convert(Money::new(1000, Currency::JPY)).unwrap(),
+            rub(610)
+        );
+        let to_yen: Rate = "RUB/JPY 1.6".parse().unwrap();
+        assert_eq!(
+            to_yen.convert(rub(10)).unwrap(),
+            Money::new(16, Currency::JPY)
+        );
+
+        for invalid in [
+            "EUR/RUB",
+            "EUR/EUR 1",
+            "EUR/RUB 0",
+            "EUR/RUB -1",
+            "EUR-RUB 2",
+        ] {
+            assert!(invalid.parse::<Rate>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_rate_table_lists_each_direction() {
+        let table: RateTable = "# курсы на 2024-05-01\nEUR/RUB 98.50\n\nRUB/EUR 0.0099\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            table.get(Currency::EUR, Currency::RUB).unwrap().to_string(),
+            "EUR/RUB 98.50"
+        );
+        assert!(matches!(
+            table.get(Currency::USD, Currency::RUB),
+            Err(BankError::RateNotFound {
+                from: Currency::USD,
+                to: Currency::RUB
+            })
+        ));
+
+        match "EUR/RUB 98\nUSD/RUB x\nEUR/RUB 99".parse::<RateTable>() {
+            Err(BankError::CorruptedData(errors)) => {
+                let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+                assert_eq!(lines, [2, 3]);
+            }
+            other => panic!("ожидалась ошибка CorruptedData, получено {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+};
+
+use crate::{clock::Timestamp, journal::JournalRecord};
+
+/// Identifier assigned to every applied transaction
+pub type TxId = u64;
+
+/// How many of the most recent idempotency keys are remembered
+pub const IDEMPOTENCY_KEYS_KEPT: usize = 10_000;
+
+/// Confirmation that a transaction was applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    pub id: TxId,
+    pub applied_at: Timestamp,
+    /// Free-text comment or external reference supplied by the caller
+    pub memo: Option<String>,
+    /// Client-supplied key that makes retries of the same request harmless
+    pub idempotency_key: Option<String>,
+}
+
+impl fmt::Display for Receipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "транзакция #{} от {}", self.id, self.applied_at)?;
+        if let Some(memo) = &self.memo {
+            write!(f, " ({})", memo)?;
+        }
+        Ok(())
+    }
+}
+
+/// A transaction applied to `Storage`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxEntry {
+    pub receipt: Receipt,
+    pub record: JournalRecord,
+    /// The transaction this one compensates, if it is a reversal
+    pub reverses: Option<TxId>,
+    /// The reversal that compensated this transaction
+    pub reversed_by: Option<TxId>,
+}
+
+impl TxEntry {
+    pub fn id(&self) -> TxId {
+        self.receipt.id
+    }
+
+    /// The entry as it is persisted: a reversal is stored as `Reverse` of
+    /// the original, so replaying it restores the link between the two
+    pub fn posted(&self) -> JournalRecord {
+        let record = match self.reverses {
+            Some(id) => JournalRecord::Reverse { id },
+            None => self.record.clone(),
+        };
+        JournalRecord::Posted {
+            receipt: self.receipt.clone(),
+            record: Box::new(record),
+        }
+    }
+}
+
+/// Applied transactions by ID, with links between originals and their reversals.
+///
+/// IDs are handed out in application order and never reused. Records
+/// persisted with their receipt (`JournalRecord::Posted`) keep their ID,
+/// time and memo when the journal or the database is replayed. Snapshots
+/// keep every transaction, so a transaction folded into one can still be
+/// reversed.
+#[derive(Debug)]
+pub struct TxLog {
+    entries: BTreeMap<TxId, TxEntry>,
+    next_id: TxId,
+    /// Транзакции по ключам идемпотентности
+    keys: HashMap<String, (Receipt, JournalRecord)>,
+    /// Ключи в порядке появления, чтобы забывать самые старые
+    key_order: VecDeque<String>,
+}
+
+impl TxLog {
+    pub fn new() -> Self {
+        TxLog {
+            entries: BTreeMap::new(),
+            next_id: 1,
+            keys: HashMap::new(),
+            key_order: VecDeque::new(),
+        }
+    }
+
+    /// Rebuilds the log from persisted records, oldest first, without
+    /// touching any balances. Account management records are skipped.
+    pub fn rebuild(records: impl IntoIterator<Item = JournalRecord>) -> Self {
+        let mut log = TxLog::new();
+        for record in records {
+            log.restore(record);
+        }
+        log
+    }
+
+    /// Adds one persisted record to the log, as `rebuild` does
+    pub(crate) fn restore(&mut self, record: JournalRecord) {
+        let (receipt, record) = match record {
+            JournalRecord::Posted { receipt, record } => (receipt, *record),
+            record => (self.next_receipt(Timestamp::default(), None), record),
+        };
+        match record {
+            JournalRecord::Reverse { id } => {
+                let inverse = self
+                    .get(id)
+                    .and_then(|entry| entry.record.to_transaction())
+                    .map(|tx| tx.inverse().journal_record());
+                if let Some(inverse) = inverse {
+                    self.push_reversal(inverse, receipt, id);
+                }
+            }
+            record if record.to_transaction().is_some() => self.push(record, receipt),
+            _ => {}
+        }
+    }
+
+    pub fn get(&self, id: TxId) -> Option<&TxEntry> {
+        self.entries.get(&id)
+    }
+
+    /// Iterates over the transactions in the order they were applied
+    pub fn iter(&self) -> impl Iterator<Item = &TxEntry> + '_ {
+        self.entries.values()
+    }
+
+    /// The ID the next applied transaction will receive
+    pub fn next_id(&self) -> TxId {
+        self.next_id
+    }
+
+    pub(crate) fn set_next_id(&mut self, id: TxId) {
+        self.next_id = id;
+    }
+
+    /// The receipt and transaction previously applied under `key`
+    pub fn find_key(&self, key: &str) -> Option<(&Receipt, &JournalRecord)> {
+        self.keys
+            .get(key)
+            .map(|(receipt, record)| (receipt, record))
+    }
+
+    /// Remembered idempotency keys, oldest first
+    pub fn keys(&self) -> impl Iterator<Item = (&Receipt, &JournalRecord)> + '_ {
+        self.key_order.iter().filter_map(|key| self.find_key(key))
+    }
+
+    /// Remembers the key of an applied transaction, forgetting the oldest
+    /// keys beyond `IDEMPOTENCY_KEYS_KEPT`
+    pub(crate) fn remember_key(&mut self, receipt: Receipt, record: JournalRecord) {
+        let Some(key) = receipt.idempotency_key.clone() else {
+            return;
+        };
+        if self.keys.insert(key.clone(), (receipt, record)).is_none() {
+            self.key_order.push_back(key);
+        }
+        while self.key_order.len() > IDEMPOTENCY_KEYS_KEPT {
+            if let Some(oldest) = self.key_order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+    }
+
+    /// Forgets every transaction from `id` on, as if it had never been
+    /// applied; used to roll back changes that could not be persisted
+    pub(crate) fn truncate(&mut self, id: TxId) {
+        for (_, entry) in self.entries.split_off(&id) {
+            if let Some(original) = entry.reverses
+                && let Some(original) = self.entries.get_mut(&original)
+            {
+                original.reversed_by = None;
+            }
+            if let Some(key) = &entry.receipt.idempotency_key
+                && self
+                    .keys
+                    .get(key)
+                    .is_some_and(|(receipt, _)| receipt.id == entry.id())
+            {
+                self.keys.remove(key);
+                self.key_order.retain(|k| k != key);
+            }
+        }
+        self.next_id = self.next_id.min(id);
+    }
+
+    /// Issues a receipt with the next free ID
+    pub(crate) fn next_receipt(&self, applied_at: Timestamp, memo: Option<&str>) -> Receipt {
+        Receipt {
+            id: self.next_id,
+            applied_at,
+            idempotency_key: None,
+            // Комментарий хранится одной строкой журнала
+            memo: memo
+                .map(|m| m.replace(['\r', '\n'], " ").trim().to_string())
+                .filter(|m| !m.is_empty()),
+        }
+    }
+
+    /// Records an applied transaction under the receipt's ID
+    pub(crate) fn push(&mut self, record: JournalRecord, receipt: Receipt) {
+        self.insert(record, receipt, None);
+    }
+
+    /// Records the reversal of `original` and links the two entries
+    pub(crate) fn push_reversal(
+        &mut self,
+        record: JournalRecord,
+        receipt: Receipt,
+        original: TxId,
+    ) {
+        let id = receipt.id;
+        self.insert(record, receipt, Some(original));
+        if let Some(entry) = self.entries.get_mut(&original) {
+            entry.reversed_by = Some(id);
+        }
+    }
+
+    fn insert(&mut self, record: JournalRecord, receipt: Receipt, reverses: Option<TxId>) {
+        self.next_id = self.next_id.max(receipt.id + 1);
+        self.remember_key(receipt.clone(), record.clone());
+        self.entries.insert(
+            receipt.id,
+            TxEntry {
+                receipt,
+                record,
+                reverses,
+                reversed_by: None,
+            },
+        );
+    }
+}
+
+impl Default for TxLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,74 @@
+use std::{fmt, str::FromStr};
+
+use crate::{clock::Timestamp, error::BankError, money::Money, storage::Name};
+
+/// Identifier of a hold, never reused
+pub type HoldId = u64;
+
+/// Money reserved on an account for a later capture.
+///
+/// A hold lowers the available balance but not the ledger balance; nothing
+/// is posted until it is captured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hold {
+    pub id: HoldId,
+    pub account: Name,
+    pub amount: Money,
+    pub placed_at: Timestamp,
+    /// After this moment the hold no longer reserves anything
+    pub expires_at: Option<Timestamp>,
+}
+
+impl Hold {
+    /// Whether the hold still reserves money at `at`
+    pub fn is_active(&self, at: Timestamp) -> bool {
+        self.expires_at.is_none_or(|expires_at| at < expires_at)
+    }
+}
+
+/// Stored as `id,account,amount,placed_at,expires_at`; expires_at is empty
+/// for a hold without expiry
+impl fmt::Display for Hold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},",
+            self.id,
+            self.account,
+            self.amount,
+            self.placed_at.unix()
+        )?;
+        if let Some(expires_at) = self.expires_at {
+            write!(f, "{}", expires_at.unix())?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Hold {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BankError::Parse(format!("некорректная блокировка '{}'", s));
+        let [id, account, amount, placed_at, expires_at] = s.split(',').collect::<Vec<_>>()[..]
+        else {
+            return Err(invalid());
+        };
+        let unix = |field: &str| {
+            field
+                .parse()
+                .map(Timestamp::from_unix)
+                .map_err(|_| invalid())
+        };
+        Ok(Hold {
+            id: id.parse().map_err(|_| invalid())?,
+            account: account.to_string(),
+            amount: amount.parse()?,
+            placed_at: unix(placed_at)?,
+            expires_at: match expires_at {
+                "" => None,
+                at => Some(unix(at)?),
+            },
+        })
+    }
+}
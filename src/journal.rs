@@ -0,0 +1,1003 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    account::{AccountLimits, AccountStatus},
+    balance_manager::BalanceManager,
+    clock::Timestamp,
+    customer::{Customer, CustomerId},
+    error::{BankError, LineError},
+    exchange::Rate,
+    history::{Receipt, TxId},
+    hold::{Hold, HoldId},
+    money::{Currency, Money},
+    storage::{Name, Storage},
+    transaction::{Batch, Booking, Capture, Deposit, Exchange, Transaction, Transfer, Withdraw},
+    user_manager::UserManager,
+};
+
+/// A single applied mutation recorded in the journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalRecord {
+    /// A customer with a same-named account; `opened_at` is missing in
+    /// journals written before it was recorded
+    AddUser {
+        name: Name,
+        balance: Money,
+        opened_at: Option<Timestamp>,
+    },
+    RemoveUser {
+        name: Name,
+    },
+    /// A new customer, stored with its ID so replay links the same accounts
+    AddCustomer(Customer),
+    OpenAccount {
+        name: Name,
+        owners: Vec<CustomerId>,
+        currency: Currency,
+        opened_at: Option<Timestamp>,
+    },
+    /// The account was frozen, marked dormant or made active again
+    SetStatus {
+        name: Name,
+        status: AccountStatus,
+    },
+    /// New limits of the account, replacing the previous ones
+    SetLimits {
+        name: Name,
+        limits: AccountLimits,
+    },
+    /// New bank-wide limits for accounts in `currency`
+    DefaultLimits {
+        currency: Currency,
+        limits: AccountLimits,
+    },
+    /// The account was closed, its balance paid out to `payout`
+    CloseAccount {
+        name: Name,
+        payout: Option<Name>,
+    },
+    Deposit {
+        account: Name,
+        amount: Money,
+    },
+    Withdraw {
+        account: Name,
+        amount: Money,
+    },
+    Transfer {
+        from: Name,
+        to: Name,
+        amount: Money,
+    },
+    /// Money converted between accounts in different currencies, stored
+    /// with both amounts and the rate used
+    Exchange {
+        from: Name,
+        to: Name,
+        amount: Money,
+        converted: Money,
+        rate: Rate,
+    },
+    /// A general ledger entry, such as a fee or interest
+    Booking {
+        debit: Name,
+        credit: Name,
+        amount: Money,
+    },
+    /// Money reserved on an account, stored with its ID so a capture
+    /// recorded later finds it
+    Authorize(Hold),
+    /// A hold removed without taking the money
+    Release {
+        hold: HoldId,
+    },
+    /// Settlement of a hold; `to` is empty for a payout in cash
+    Capture {
+        hold: HoldId,
+        account: Name,
+        amount: Money,
+        to: Option<Name>,
+    },
+    /// Steps of an atomic batch, stored as `batch;step;step...`
+    Batch(Vec<JournalRecord>),
+    /// Reversal of the transaction with the given ID
+    Reverse {
+        id: TxId,
+    },
+    /// A transaction or reversal together with the receipt it was issued,
+    /// so replay keeps its ID, time and memo
+    Posted {
+        receipt: Receipt,
+        record: Box<JournalRecord>,
+    },
+}
+
+impl JournalRecord {
+    /// Applies the recorded mutation to the storage
+    pub fn apply(&self, storage: &mut Storage) -> Result<(), BankError> {
+        match self {
+            // Счёт, клиент и начальный вклад получают исходное время открытия
+            JournalRecord::AddUser {
+                name,
+                balance,
+                opened_at,
+            } => storage.at_time(*opened_at, |storage| {
+                UserManager::add_user(storage, name.clone())?;
+                if !balance.is_zero()
+                    && let Err(e) = BalanceManager::deposit(storage, name, *balance)
+                {
+                    // Не оставляем наполовину созданного пользователя
+                    let _ = UserManager::remove_user(storage, name);
+                    return Err(e);
+                }
+                Ok(())
+            }),
+            JournalRecord::RemoveUser { name } => {
+                UserManager::remove_user(storage, name).map(|_| ())
+            }
+            JournalRecord::AddCustomer(customer) => {
+                storage.load_customer(customer.clone());
+                Ok(())
+            }
+            JournalRecord::OpenAccount {
+                name,
+                owners,
+                currency,
+                opened_at,
+            } => storage.at_time(*opened_at, |storage| {
+                UserManager::open_account(storage, name.clone(), owners, *currency).map(|_| ())
+            }),
+            JournalRecord::SetStatus { name, status } => match status {
+                AccountStatus::Active => UserManager::unfreeze(storage, name),
+                AccountStatus::Frozen => UserManager::freeze(storage, name),
+                AccountStatus::Dormant => UserManager::mark_dormant(storage, name),
+                AccountStatus::Closed => {
+                    UserManager::close_account(storage, name, None).map(|_| ())
+                }
+            },
+            JournalRecord::SetLimits { name, limits } => storage.set_limits_internal(name, *limits),
+            JournalRecord::DefaultLimits { currency, limits } => {
+                storage.set_default_limits(*currency, *limits)
+            }
+            JournalRecord::CloseAccount { name, payout } => {
+                UserManager::close_account(storage, name, payout.as_ref()).map(|_| ())
+            }
+            JournalRecord::Authorize(hold) => {
+                storage.load_hold(hold.clone());
+                Ok(())
+            }
+            JournalRecord::Release { hold } => BalanceManager::release(storage, *hold).map(|_| ()),
+            JournalRecord::Reverse { id } => storage.reverse(*id, None).map(|_| ()),
+            JournalRecord::Posted { receipt, record } => match record.as_ref() {
+                JournalRecord::Reverse { id } => storage.reverse_as(*id, receipt.clone()),
+                record => storage.execute_as(record.transaction()?.as_ref(), receipt.clone()),
+            }
+            .map(|_| ()),
+            // Записи без квитанции получают новые номера в том же порядке
+            JournalRecord::Deposit { .. }
+            | JournalRecord::Withdraw { .. }
+            | JournalRecord::Transfer { .. }
+            | JournalRecord::Exchange { .. }
+            | JournalRecord::Booking { .. }
+            | JournalRecord::Capture { .. }
+            | JournalRecord::Batch(_) => storage
+                .execute(self.transaction()?.as_ref(), None)
+                .map(|_| ()),
+        }
+    }
+
+    /// Like `to_transaction`, but a record read from a journal or database
+    /// that is not a transaction (e.g. a batch with a `remove` step) is an error
+    fn transaction(&self) -> Result<Box<dyn Transaction>, BankError> {
+        self.to_transaction()
+            .ok_or_else(|| BankError::Parse(format!("запись не является транзакцией: '{}'", self)))
+    }
+
+    /// Rebuilds the transaction this record was produced from.
+    /// Account management records and reversals are not transactions and return None.
+    pub fn to_transaction(&self) -> Option<Box<dyn Transaction>> {
+        match self {
+            JournalRecord::AddUser { .. }
+            | JournalRecord::RemoveUser { .. }
+            | JournalRecord::AddCustomer(_)
+            | JournalRecord::OpenAccount { .. }
+            | JournalRecord::SetStatus { .. }
+            | JournalRecord::SetLimits { .. }
+            | JournalRecord::DefaultLimits { .. }
+            | JournalRecord::CloseAccount { .. }
+            | JournalRecord::Authorize(_)
+            | JournalRecord::Release { .. }
+            | JournalRecord::Reverse { .. }
+            | JournalRecord::Posted { .. } => None,
+            JournalRecord::Deposit { account, amount } => Some(Box::new(Deposit {
+                account: account.clone(),
+                amount: *amount,
+            })),
+            JournalRecord::Withdraw { account, amount } => Some(Box::new(Withdraw {
+                account: account.clone(),
+                amount: *amount,
+            })),
+            JournalRecord::Transfer { from, to, amount } => Some(Box::new(Transfer {
+                from: from.clone(),
+                to: to.clone(),
+                amount: *amount,
+            })),
+            JournalRecord::Exchange {
+                from,
+                to,
+                amount,
+                converted,
+                rate,
+            } => Some(Box::new(Exchange {
+                from: from.clone(),
+                to: to.clone(),
+                amount: *amount,
+                converted: *converted,
+                rate: *rate,
+            })),
+            JournalRecord::Booking {
+                debit,
+                credit,
+                amount,
+            } => Some(Box::new(Booking {
+                debit: debit.clone(),
+                credit: credit.clone(),
+                amount: *amount,
+            })),
+            JournalRecord::Capture {
+                hold,
+                account,
+                amount,
+                to,
+            } => Some(Box::new(Capture {
+                hold: *hold,
+                account: account.clone(),
+                amount: *amount,
+                to: to.clone(),
+            })),
+            JournalRecord::Batch(records) => {
+                let steps = records
+                    .iter()
+                    .map(JournalRecord::to_transaction)
+                    .collect::<Option<Vec<_>>>()?;
+                Some(Box::new(Batch::new(steps)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for JournalRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalRecord::AddUser {
+                name,
+                balance,
+                opened_at,
+            } => write!(f, "add,{},{}{}", name, balance, time_field(opened_at)),
+            JournalRecord::RemoveUser { name } => write!(f, "remove,{}", name),
+            JournalRecord::AddCustomer(customer) => write!(f, "customer,{}", customer),
+            JournalRecord::OpenAccount {
+                name,
+                owners,
+                currency,
+                opened_at,
+            } => {
+                let owners: Vec<String> = owners.iter().map(|id| id.to_string()).collect();
+                write!(
+                    f,
+                    "open,{},{},{}{}",
+                    name,
+                    currency,
+                    owners.join(";"),
+                    time_field(opened_at)
+                )
+            }
+            JournalRecord::SetStatus { name, status } => write!(f, "status,{},{}", name, status),
+            JournalRecord::SetLimits { name, limits } => write!(f, "limits,{},{}", name, limits),
+            JournalRecord::DefaultLimits { currency, limits } => {
+                write!(f, "default_limits,{},{}", currency, limits)
+            }
+            JournalRecord::CloseAccount { name, payout } => {
+                write!(f, "close,{},{}", name, payout.as_deref().unwrap_or(""))
+            }
+            JournalRecord::Deposit { account, amount } => {
+                write!(f, "deposit,{},{}", account, amount)
+            }
+            JournalRecord::Withdraw { account, amount } => {
+                write!(f, "withdraw,{},{}", account, amount)
+            }
+            JournalRecord::Transfer { from, to, amount } => {
+                write!(f, "transfer,{},{},{}", from, to, amount)
+            }
+            JournalRecord::Exchange {
+                from,
+                to,
+                amount,
+                converted,
+                rate,
+            } => write!(
+                f,
+                "exchange,{},{},{},{},{}",
+                from, to, amount, converted, rate
+            ),
+            JournalRecord::Booking {
+                debit,
+                credit,
+                amount,
+            } => write!(f, "book,{},{},{}", debit, credit, amount),
+            JournalRecord::Authorize(hold) => write!(f, "hold,{}", hold),
+            JournalRecord::Release { hold } => write!(f, "release,{}", hold),
+            JournalRecord::Capture {
+                hold,
+                account,
+                amount,
+                to,
+            } => write!(
+                f,
+                "capture,{},{},{},{}",
+                hold,
+                account,
+                amount,
+                to.as_deref().unwrap_or("")
+            ),
+            JournalRecord::Batch(records) => {
+                write!(f, "batch")?;
+                for record in records {
+                    write!(f, ";{}", record)?;
+                }
+                Ok(())
+            }
+            JournalRecord::Reverse { id } => write!(f, "reverse,{}", id),
+            JournalRecord::Posted { receipt, record } => write!(
+                f,
+                "posted;{};{};{};{};{}",
+                receipt.id,
+                receipt.applied_at.unix(),
+                escape(receipt.idempotency_key.as_deref().unwrap_or("")),
+                escape(receipt.memo.as_deref().unwrap_or("")),
+                record
+            ),
+        }
+    }
+}
+
+impl FromStr for JournalRecord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(posted) = s.strip_prefix("posted;") {
+            let [id, applied_at, key, memo, record] = posted.splitn(5, ';').collect::<Vec<_>>()[..]
+            else {
+                return Err(format!("некорректная квитанция '{}'", s));
+            };
+            let receipt = Receipt {
+                id: id
+                    .parse()
+                    .map_err(|_| format!("некорректный номер транзакции '{}'", id))?,
+                applied_at: applied_at
+                    .parse()
+                    .map(Timestamp::from_unix)
+                    .map_err(|_| format!("некорректное время '{}'", applied_at))?,
+                idempotency_key: unescape(key),
+                memo: unescape(memo),
+            };
+            return Ok(JournalRecord::Posted {
+                receipt,
+                record: Box::new(record.parse()?),
+            });
+        }
+
+        if let Some(customer) = s.strip_prefix("customer,") {
+            return customer
+                .parse()
+                .map(JournalRecord::AddCustomer)
+                .map_err(|e: BankError| e.to_string());
+        }
+
+        if let Some(hold) = s.strip_prefix("hold,") {
+            return hold
+                .parse()
+                .map(JournalRecord::Authorize)
+                .map_err(|e: BankError| e.to_string());
+        }
+
+        if let Some(steps) = s.strip_prefix("batch") {
+            let records = steps
+                .split(';')
+                .skip(1)
+                .map(str::parse)
+                .collect::<Result<Vec<JournalRecord>, _>>()?;
+            if records.iter().any(|r| r.to_transaction().is_none()) {
+                return Err(format!("пакет может содержать только транзакции: '{}'", s));
+            }
+            return Ok(JournalRecord::Batch(records));
+        }
+
+        let parts: Vec<&str> = s.split(',').collect();
+        let amount = |field: &str| -> Result<Money, String> {
+            field
+                .parse()
+                .map_err(|e| format!("некорректная сумма '{}': {}", field, e))
+        };
+        let time = |field: &str| -> Result<Timestamp, String> {
+            field
+                .parse()
+                .map(Timestamp::from_unix)
+                .map_err(|_| format!("некорректное время '{}'", field))
+        };
+
+        match parts.as_slice() {
+            ["add", name, balance, opened_at @ ..] if opened_at.len() <= 1 => {
+                Ok(JournalRecord::AddUser {
+                    name: name.to_string(),
+                    balance: amount(balance)?,
+                    opened_at: opened_at.first().map(|at| time(at)).transpose()?,
+                })
+            }
+            ["remove", name] => Ok(JournalRecord::RemoveUser {
+                name: name.to_string(),
+            }),
+            ["open", name, currency, owners, opened_at @ ..] if opened_at.len() <= 1 => {
+                Ok(JournalRecord::OpenAccount {
+                    name: name.to_string(),
+                    currency: currency.parse().map_err(|e: BankError| e.to_string())?,
+                    owners: owners
+                        .split(';')
+                        .map(|id| {
+                            id.parse()
+                                .map_err(|_| format!("некорректный номер клиента '{}'", id))
+                        })
+                        .collect::<Result<_, _>>()?,
+                    opened_at: opened_at.first().map(|at| time(at)).transpose()?,
+                })
+            }
+            ["status", name, status] => Ok(JournalRecord::SetStatus {
+                name: name.to_string(),
+                status: status.parse().map_err(|e: BankError| e.to_string())?,
+            }),
+            ["limits", name, limits] => Ok(JournalRecord::SetLimits {
+                name: name.to_string(),
+                limits: limits.parse().map_err(|e: BankError| e.to_string())?,
+            }),
+            ["default_limits", currency, limits] => Ok(JournalRecord::DefaultLimits {
+                currency: currency.parse().map_err(|e: BankError| e.to_string())?,
+                limits: limits.parse().map_err(|e: BankError| e.to_string())?,
+            }),
+            ["close", name, payout] => Ok(JournalRecord::CloseAccount {
+                name: name.to_string(),
+                payout: Some(payout.to_string()).filter(|p| !p.is_empty()),
+            }),
+            ["deposit", account, value] => Ok(JournalRecord::Deposit {
+                account: account.to_string(),
+                amount: amount(value)?,
+            }),
+            ["withdraw", account, value] => Ok(JournalRecord::Withdraw {
+                account: account.to_string(),
+                amount: amount(value)?,
+            }),
+            ["transfer", from, to, value] => Ok(JournalRecord::Transfer {
+                from: from.to_string(),
+                to: to.to_string(),
+                amount: amount(value)?,
+            }),
+            ["exchange", from, to, value, converted, rate] => Ok(JournalRecord::Exchange {
+                from: from.to_string(),
+                to: to.to_string(),
+                amount: amount(value)?,
+                converted: amount(converted)?,
+                rate: rate.parse().map_err(|e: BankError| e.to_string())?,
+            }),
+            ["book", debit, credit, value] => Ok(JournalRecord::Booking {
+                debit: debit.to_string(),
+                credit: credit.to_string(),
+                amount: amount(value)?,
+            }),
+            ["release", id] => Ok(JournalRecord::Release {
+                hold: id
+                    .parse()
+                    .map_err(|_| format!("некорректный номер блокировки '{}'", id))?,
+            }),
+            ["capture", id, account, value, to] => Ok(JournalRecord::Capture {
+                hold: id
+                    .parse()
+                    .map_err(|_| format!("некорректный номер блокировки '{}'", id))?,
+                account: account.to_string(),
+                amount: amount(value)?,
+                to: Some(to.to_string()).filter(|to| !to.is_empty()),
+            }),
+            ["reverse", id] => Ok(JournalRecord::Reverse {
+                id: id
+                    .parse()
+                    .map_err(|_| format!("некорректный номер транзакции '{}'", id))?,
+            }),
+            _ => Err(format!("неизвестная запись '{}'", s)),
+        }
+    }
+}
+
+/// Append-only write-ahead journal of applied mutations.
+///
+/// Every record is stored as `seq,record` and flushed to disk before `append`
+/// returns. On startup the journal is replayed on top of the CSV snapshot;
+/// records already folded into the snapshot (seq <= the snapshot's
+/// `journal_seq`) are skipped.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    last_seq: u64,
+}
+
+impl Journal {
+    /// Opens the journal, replaying every record newer than the snapshot into `storage`
+    pub fn open<P: AsRef<Path>>(path: P, storage: &mut Storage) -> Result<Journal, BankError> {
+        let path = path.as_ref().to_path_buf();
+        let mut last_seq = storage.journal_seq;
+
+        if path.exists() {
+            let reader = io::BufReader::new(File::open(&path)?);
+            let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+            let complete = ends_with_newline(&path)?;
+            let mut torn = false;
+
+            for (index, line) in lines.iter().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // Недописанная последняя запись (сбой во время append) не была
+                // подтверждена пользователю, поэтому её можно отбросить
+                let is_torn_tail = index + 1 == lines.len() && !complete;
+
+                match parse_entry(line) {
+                    Ok((seq, record)) => {
+                        if seq <= last_seq {
+                            continue;
+                        }
+                        record.apply(storage).map_err(|e| {
+                            BankError::CorruptedData(vec![LineError {
+                                line: index + 1,
+                                reason: e.to_string(),
+                            }])
+                        })?;
+                        last_seq = seq;
+                    }
+                    Err(_) if is_torn_tail => {
+                        torn = true;
+                        break;
+                    }
+                    Err(reason) => {
+                        return Err(BankError::CorruptedData(vec![LineError {
+                            line: index + 1,
+                            reason,
+                        }]));
+                    }
+                }
+            }
+
+            if !complete {
+                // Дописываем перевод строки или отрезаем оборванный хвост,
+                // чтобы следующие записи начинались с новой строки
+                let keep = if torn {
+                    &lines[..lines.len() - 1]
+                } else {
+                    &lines[..]
+                };
+                rewrite(&path, keep)?;
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Journal {
+            path,
+            file,
+            last_seq,
+        })
+    }
+
+    /// Appends a record that has already been applied to the storage
+    /// and returns once it is on disk. A record that could not be written is
+    /// cut off the journal, so it is not replayed after a restart either.
+    pub fn append(&mut self, record: &JournalRecord) -> Result<(), BankError> {
+        let seq = self.last_seq + 1;
+        let len = self.file.metadata()?.len();
+        let written = writeln!(self.file, "{},{}", seq, record).and_then(|_| self.file.sync_data());
+        if let Err(e) = written {
+            let _ = self.file.set_len(len);
+            return Err(e.into());
+        }
+        self.last_seq = seq;
+        Ok(())
+    }
+
+    /// Commits `storage`, noting that every record appended so far is part of
+    /// what it persists, so reopening does not replay them a second time
+    pub fn commit(&self, storage: &mut Storage) -> Result<(), BankError> {
+        storage.journal_seq = self.last_seq;
+        storage.commit()
+    }
+
+    /// Folds the journal into a fresh snapshot and empties the journal
+    pub fn compact(&mut self, storage: &mut Storage, snapshot: &str) -> Result<(), BankError> {
+        // Снимок запоминает последнюю вошедшую в него запись: если сбой случится
+        // до очистки журнала, повторное воспроизведение пропустит эти записи
+        storage.journal_seq = self.last_seq;
+        storage.try_save(snapshot)?;
+
+        rewrite(&self.path, &[])?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+}
+
+fn parse_entry(line: &str) -> Result<(u64, JournalRecord), String> {
+    let (seq, record) = line
+        .split_once(',')
+        .ok_or_else(|| format!("некорректная запись '{}'", line))?;
+    let seq = seq
+        .parse()
+        .map_err(|_| format!("некорректный номер записи '{}'", seq))?;
+    Ok((seq, record.parse()?))
+}
+
+/// Optional trailing time field, in Unix seconds
+fn time_field(at: &Option<Timestamp>) -> String {
+    at.map(|at| format!(",{}", at.unix())).unwrap_or_default()
+}
+
+/// Hides the field separator and line breaks inside free-text fields
+fn escape(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace(';', "%3B")
+        .replace('\n', "%0A")
+        .replace('\r', "%0D")
+}
+
+/// Inverse of `escape`; an empty field means the value is absent
+fn unescape(field: &str) -> Option<String> {
+    let text = field
+        .replace("%0D", "\r")
+        .replace("%0A", "\n")
+        .replace("%3B", ";")
+        .replace("%25", "%");
+    Some(text).filter(|t| !t.is_empty())
+}
+
+fn ends_with_newline(path: &Path) -> io::Result<bool> {
+    let data = std::fs::read(path)?;
+    Ok(data.is_empty() || data.ends_with(b"\n"))
+}
+
+/// Atomically replaces the journal contents
+fn rewrite(path: &Path, lines: &[String]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    {
+        let mut out = File::create(&tmp)?;
+        for line in lines {
+            writeln!(out, "{}", line)?;
+        }
+        out.sync_all()?;
+    }
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{clock::FixedClock, money::rub, storage::LoadMode};
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("bank-{}-{}", name, std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let records = [
+            JournalRecord::AddUser {
+                name: "John".to_string(),
+                balance: rub(10),
+                opened_at: None,
+            },
+            JournalRecord::AddUser {
+                name: "John".to_string(),
+                balance: rub(10),
+                opened_at: Some(Timestamp::from_unix(1_700_000_000)),
+            },
+            JournalRecord::RemoveUser {
+                name: "John".to_string(),
+            },
+            JournalRecord::AddCustomer(Customer {
+                id: 3,
+                since: Timestamp::from_unix(1_700_000_000),
+                name: "Анна Петрова".to_string(),
+            }),
+            JournalRecord::OpenAccount {
+                name: "anna-joint".to_string(),
+                owners: vec![3, 5],
+                currency: Currency::EUR,
+                opened_at: Some(Timestamp::from_unix(1_700_000_000)),
+            },
+            JournalRecord::SetStatus {
+                name: "anna-joint".to_string(),
+                status: AccountStatus::Frozen,
+            },
+            JournalRecord::CloseAccount {
+                name: "anna-joint".to_string(),
+                payout: Some("Alice".to_string()),
+            },
+            JournalRecord::CloseAccount {
+                name: "Bob".to_string(),
+                payout: None,
+            },
+            JournalRecord::SetLimits {
+                name: "Alice".to_string(),
+                limits: AccountLimits {
+                    overdraft: Some(rub(100)),
+                    per_transaction: None,
+                    daily: Some(rub(20)),
+                },
+            },
+            JournalRecord::DefaultLimits {
+                currency: Currency::RUB,
+                limits: AccountLimits {
+                    per_transaction: Some(rub(500)),
+                    ..AccountLimits::default()
+                },
+            },
+            JournalRecord::Deposit {
+                account: "Alice".to_string(),
+                amount: rub(5),
+            },
+            JournalRecord::Withdraw {
+                account: "Alice".to_string(),
+                amount: rub(3),
+            },
+            JournalRecord::Transfer {
+                from: "Alice".to_string(),
+                to: "Bob".to_string(),
+                amount: rub(1),
+            },
+            JournalRecord::Booking {
+                debit: "Alice".to_string(),
+                credit: "@fees:RUB".to_string(),
+                amount: rub(1),
+            },
+            JournalRecord::Exchange {
+                from: "Alice".to_string(),
+                to: "alice-eur".to_string(),
+                amount: rub(100),
+                converted: Money::new(99, Currency::EUR),
+                rate: "RUB/EUR 0.0099".parse().unwrap(),
+            },
+            JournalRecord::Batch(vec![
+                JournalRecord::Withdraw {
+                    account: "Alice".to_string(),
+                    amount: rub(2),
+                },
+                JournalRecord::Deposit {
+                    account: "Bob".to_string(),
+                    amount: rub(2),
+                },
+            ]),
+            JournalRecord::Authorize(Hold {
+                id: 4,
+                account: "Alice".to_string(),
+                amount: rub(12),
+                placed_at: Timestamp::from_unix(1_700_000_000),
+                expires_at: Some(Timestamp::from_unix(1_700_086_400)),
+            }),
+            JournalRecord::Release { hold: 4 },
+            JournalRecord::Capture {
+                hold: 5,
+                account: "Alice".to_string(),
+                amount: rub(7),
+                to: None,
+            },
+            JournalRecord::Capture {
+                hold: 6,
+                account: "Alice".to_string(),
+                amount: rub(7),
+                to: Some("Bob".to_string()),
+            },
+            JournalRecord::Reverse { id: 7 },
+            JournalRecord::Posted {
+                receipt: Receipt {
+                    id: 8,
+                    applied_at: Timestamp::from_unix(1_700_000_000),
+                    memo: Some("аванс; 50%".to_string()),
+                    idempotency_key: Some("req-1\n%3B".to_string()),
+                },
+                record: Box::new(JournalRecord::Batch(vec![JournalRecord::Deposit {
+                    account: "Bob".to_string(),
+                    amount: rub(4),
+                }])),
+            },
+        ];
+
+        for record in records {
+            assert_eq!(record.to_string().parse::<JournalRecord>(), Ok(record));
+        }
+    }
+
+    #[test]
+    fn test_replay_and_compact() {
+        let journal_path = temp_path("journal.log");
+        let snapshot = temp_path("journal-snapshot.csv");
+
+        let mut storage = Storage::new();
+        let mut journal = Journal::open(&journal_path, &mut storage).unwrap();
+        for record in [
+            JournalRecord::AddUser {
+                name: "Alice".to_string(),
+                balance: rub(100),
+                opened_at: None,
+            },
+            JournalRecord::AddUser {
+                name: "Bob".to_string(),
+                balance: rub(0),
+                opened_at: None,
+            },
+            JournalRecord::Transfer {
+                from: "Alice".to_string(),
+                to: "Bob".to_string(),
+                amount: rub(30),
+            },
+        ] {
+            record.apply(&mut storage).unwrap();
+            journal.append(&record).unwrap();
+        }
+
+        // Перезапуск: журнал воспроизводится поверх пустого хранилища
+        let mut replayed = Storage::new();
+        Journal::open(&journal_path, &mut replayed).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&replayed, &"Bob".to_string()),
+            Some(rub(30))
+        );
+
+        journal.compact(&mut storage, &snapshot).unwrap();
+        assert_eq!(fs::read_to_string(&journal_path).unwrap(), "");
+
+        let mut restored = Storage::try_load_data(&snapshot, LoadMode::Strict)
+            .unwrap()
+            .storage;
+        Journal::open(&journal_path, &mut restored).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&restored, &"Alice".to_string()),
+            Some(rub(70))
+        );
+
+        let _ = fs::remove_file(&journal_path);
+        let _ = fs::remove_file(&snapshot);
+    }
+
+    #[test]
+    fn test_reverse_after_compact() {
+        let journal_path = temp_path("journal-reverse.log");
+        let snapshot = temp_path("journal-reverse.csv");
+        let (alice, bob) = ("Alice".to_string(), "Bob".to_string());
+
+        let mut storage = Storage::new();
+        let mut journal = Journal::open(&journal_path, &mut storage).unwrap();
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        UserManager::add_user(&mut storage, bob.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(100)).unwrap();
+        let transfer = Transfer {
+            from: alice.clone(),
+            to: bob.clone(),
+            amount: rub(30),
+        };
+        let receipt = storage.execute(&transfer, None).unwrap();
+        journal.compact(&mut storage, &snapshot).unwrap();
+
+        // Отменяется транзакция, которая есть только в снимке
+        let reversal = storage.reverse(receipt.id, None).unwrap();
+        journal
+            .append(&JournalRecord::Posted {
+                receipt: reversal.clone(),
+                record: Box::new(JournalRecord::Reverse { id: receipt.id }),
+            })
+            .unwrap();
+
+        let mut restored = Storage::try_load_data(&snapshot, LoadMode::Strict)
+            .unwrap()
+            .storage;
+        Journal::open(&journal_path, &mut restored).unwrap();
+        assert_eq!(BalanceManager::get_balance(&restored, &bob), Some(rub(0)));
+        assert_eq!(
+            restored.history().get(receipt.id).unwrap().reversed_by,
+            Some(reversal.id)
+        );
+        assert!(matches!(
+            restored.reverse(receipt.id, None),
+            Err(BankError::AlreadyReversed { .. })
+        ));
+
+        let _ = fs::remove_file(&journal_path);
+        let _ = fs::remove_file(&snapshot);
+    }
+
+    #[test]
+    fn test_replay_skips_records_already_in_snapshot() {
+        let journal_path = temp_path("journal-seq.log");
+        fs::write(
+            &journal_path,
+            "1,deposit,Alice,10\n2,deposit,Alice,5\n3,deposit,Ali",
+        )
+        .unwrap();
+
+        let mut storage = Storage::new();
+        UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
+        BalanceManager::deposit(&mut storage, &"Alice".to_string(), rub(10)).unwrap();
+        storage.journal_seq = 1;
+
+        Journal::open(&journal_path, &mut storage).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(15))
+        );
+        // Оборванная запись удалена из журнала
+        assert_eq!(
+            fs::read_to_string(&journal_path).unwrap(),
+            "1,deposit,Alice,10\n2,deposit,Alice,5\n"
+        );
+
+        let _ = fs::remove_file(&journal_path);
+    }
+
+    #[test]
+    fn test_replay_rejects_batch_of_non_transactions() {
+        let mut storage = Storage::new();
+        UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
+
+        // Разбор такой пакет не пропустит, но собранный в коде — ошибка, а не паника
+        let record = JournalRecord::Batch(vec![JournalRecord::RemoveUser {
+            name: "Alice".to_string(),
+        }]);
+        assert!(matches!(
+            record.apply(&mut storage),
+            Err(BankError::Parse(_))
+        ));
+        assert!(BalanceManager::get_balance(&storage, &"Alice".to_string()).is_some());
+    }
+
+    #[test]
+    fn test_replay_keeps_opening_time() {
+        let opened_at = Timestamp::from_unix(1_700_000_000);
+        let mut storage = Storage::new();
+        storage.set_clock(FixedClock(Timestamp::from_unix(1_800_000_000)));
+        let add: JournalRecord = format!("add,John,100,{}", opened_at.unix())
+            .parse()
+            .unwrap();
+        add.apply(&mut storage).unwrap();
+        JournalRecord::OpenAccount {
+            name: "john-eur".to_string(),
+            owners: vec![1],
+            currency: Currency::EUR,
+            opened_at: Some(opened_at),
+        }
+        .apply(&mut storage)
+        .unwrap();
+
+        // Счета, клиент и начальный вклад датированы исходным временем, а не часами
+        for name in ["John", "john-eur"] {
+            assert_eq!(
+                storage.get_account(&name.to_string()).unwrap().opened_at,
+                opened_at
+            );
+        }
+        assert_eq!(storage.get_customer(1).unwrap().since, opened_at);
+        assert!(storage.postings().iter().all(|p| p.at == opened_at));
+        assert_eq!(storage.now(), Timestamp::from_unix(1_800_000_000));
+    }
+}
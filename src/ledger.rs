@@ -0,0 +1,375 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    error::BankError,
+    money::{Currency, Money},
+    storage::{Balance, Name},
+};
+
+/// Prefix that marks the bank's own accounts; customers cannot use it
+pub const INTERNAL_PREFIX: char = '@';
+
+/// Kind of account in the bank's chart of accounts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AccountType {
+    /// What the bank has: cash
+    Asset,
+    /// What the bank owes: customer balances
+    Liability,
+    /// The owners' stake in the bank
+    Equity,
+    /// Money the bank earns, such as fees
+    Income,
+    /// Money the bank spends, such as interest paid to customers
+    Expense,
+}
+
+impl AccountType {
+    pub const ALL: [AccountType; 5] = [
+        AccountType::Asset,
+        AccountType::Liability,
+        AccountType::Equity,
+        AccountType::Income,
+        AccountType::Expense,
+    ];
+
+    /// The side on which balances of this type grow
+    pub fn normal_side(self) -> Side {
+        match self {
+            AccountType::Asset | AccountType::Expense => Side::Debit,
+            AccountType::Liability | AccountType::Equity | AccountType::Income => Side::Credit,
+        }
+    }
+
+    /// Name of the bank's account of this type; customer accounts have no code
+    fn code(self) -> Option<&'static str> {
+        match self {
+            AccountType::Asset => Some("cash"),
+            AccountType::Liability => None,
+            AccountType::Equity => Some("equity"),
+            AccountType::Income => Some("fees"),
+            AccountType::Expense => Some("interest"),
+        }
+    }
+}
+
+impl fmt::Display for AccountType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AccountType::Asset => "Активы",
+            AccountType::Liability => "Обязательства",
+            AccountType::Equity => "Капитал",
+            AccountType::Income => "Доходы",
+            AccountType::Expense => "Расходы",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The bank's cash/clearing account for a currency: deposits bring money
+/// into it and withdrawals pay money out of it
+pub fn cash_account(currency: Currency) -> Name {
+    internal_name(AccountType::Asset, currency)
+}
+
+/// Income account that collects fees charged in a currency
+pub fn fee_account(currency: Currency) -> Name {
+    internal_name(AccountType::Income, currency)
+}
+
+/// Expense account that pays interest in a currency
+pub fn interest_account(currency: Currency) -> Name {
+    internal_name(AccountType::Expense, currency)
+}
+
+/// The owners' capital in a currency
+pub fn equity_account(currency: Currency) -> Name {
+    internal_name(AccountType::Equity, currency)
+}
+
+/// Code of the bank's currency position accounts
+const EXCHANGE_CODE: &str = "fx";
+
+/// The bank's position in a currency built up by exchanges: it grows by the
+/// currency customers sell to the bank and shrinks by what they buy. It is
+/// part of the owners' equity.
+pub fn exchange_account(currency: Currency) -> Name {
+    format!("{}{}:{}", INTERNAL_PREFIX, EXCHANGE_CODE, currency)
+}
+
+fn internal_name(kind: AccountType, currency: Currency) -> Name {
+    format!(
+        "{}{}:{}",
+        INTERNAL_PREFIX,
+        kind.code().unwrap_or_default(),
+        currency
+    )
+}
+
+/// Whether the account belongs to the bank rather than to a customer
+pub fn is_internal(name: &str) -> bool {
+    name.starts_with(INTERNAL_PREFIX)
+}
+
+/// Type of an account by its name; None for a name outside the chart
+pub fn account_type(name: &str) -> Option<AccountType> {
+    let Some(internal) = name.strip_prefix(INTERNAL_PREFIX) else {
+        return Some(AccountType::Liability);
+    };
+    let (code, currency) = internal.split_once(':')?;
+    currency.parse::<Currency>().ok()?;
+    if code == EXCHANGE_CODE {
+        return Some(AccountType::Equity);
+    }
+    AccountType::ALL
+        .into_iter()
+        .find(|kind| kind.code() == Some(code))
+}
+
+/// Column of a ledger entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Debit,
+    Credit,
+}
+
+/// The side on which an account's balance grows.
+///
+/// Customer balances are what the bank owes, so they are credit-normal;
+/// see `AccountType::normal_side` for the bank's own accounts.
+pub fn normal_side(name: &str) -> Side {
+    account_type(name).map_or(Side::Debit, AccountType::normal_side)
+}
+
+/// One account's balance placed in its debit or credit column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrialBalanceRow {
+    pub account: Name,
+    pub side: Side,
+    /// Always zero or positive; a negative balance moves to the opposite column
+    pub amount: Money,
+}
+
+/// Debit and credit totals of every account, per currency.
+///
+/// Every transaction changes both sides by the same amount, so the totals
+/// match unless balances were changed behind the ledger's back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrialBalance {
+    pub rows: Vec<TrialBalanceRow>,
+    /// (debits, credits) for each currency
+    pub totals: BTreeMap<Currency, (Money, Money)>,
+}
+
+impl TrialBalance {
+    pub(crate) fn build(
+        accounts: impl IntoIterator<Item = (Name, Balance)>,
+    ) -> Result<TrialBalance, BankError> {
+        let mut rows: Vec<TrialBalanceRow> = accounts
+            .into_iter()
+            .map(|(account, balance)| {
+                let side = match (normal_side(&account), balance.is_negative()) {
+                    (side, false) => side,
+                    (Side::Debit, true) => Side::Credit,
+                    (Side::Credit, true) => Side::Debit,
+                };
+                Ok(TrialBalanceRow {
+                    account,
+                    side,
+                    amount: if balance.is_negative() {
+                        balance.checked_neg()?
+                    } else {
+                        balance
+                    },
+                })
+            })
+            .collect::<Result<_, BankError>>()?;
+        rows.sort_by(|a, b| a.account.cmp(&b.account));
+
+        let mut totals = BTreeMap::new();
+        for row in &rows {
+            let currency = row.amount.currency();
+            let (debits, credits) = totals
+                .entry(currency)
+                .or_insert((Money::zero(currency), Money::zero(currency)));
+            match row.side {
+                Side::Debit => *debits = debits.checked_add(row.amount)?,
+                Side::Credit => *credits = credits.checked_add(row.amount)?,
+            }
+        }
+
+        Ok(TrialBalance { rows, totals })
+    }
+
+    /// True when debits equal credits in every currency
+    pub fn is_balanced(&self) -> bool {
+        self.totals
+            .values()
+            .all(|(debits, credits)| debits == credits)
+    }
+}
+
+/// Balances of every account of one type, each in its normal direction:
+/// a positive amount is a balance on the type's normal side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountGroup {
+    pub kind: AccountType,
+    pub accounts: Vec<(Name, Balance)>,
+    pub totals: BTreeMap<Currency, Money>,
+}
+
+impl AccountGroup {
+    /// Groups accounts by type in chart order; types without accounts are
+    /// left out, as are names outside the chart
+    pub(crate) fn build(
+        accounts: impl IntoIterator<Item = (Name, Balance)>,
+    ) -> Result<Vec<AccountGroup>, BankError> {
+        let mut groups: BTreeMap<AccountType, AccountGroup> = BTreeMap::new();
+        for (account, balance) in accounts {
+            let Some(kind) = account_type(&account) else {
+                continue;
+            };
+            let group = groups.entry(kind).or_insert_with(|| AccountGroup {
+                kind,
+                accounts: Vec::new(),
+                totals: BTreeMap::new(),
+            });
+            let total = group
+                .totals
+                .entry(balance.currency())
+                .or_insert(Money::zero(balance.currency()));
+            *total = total.checked_add(balance)?;
+            group.accounts.push((account, balance));
+        }
+
+        let mut groups: Vec<AccountGroup> = groups.into_values().collect();
+        for group in &mut groups {
+            group.accounts.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        Ok(groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        balance_manager::BalanceManager,
+        money::rub,
+        storage::Storage,
+        transaction::{Booking, Deposit, Transaction, Transfer},
+        user_manager::UserManager,
+    };
+
+    #[test]
+    fn test_trial_balance_stays_balanced() {
+        let mut storage = Storage::new();
+        let (alice, bob) = ("Alice".to_string(), "Bob".to_string());
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        UserManager::add_user(&mut storage, bob.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(100)).unwrap();
+        BalanceManager::withdraw(&mut storage, &alice, rub(30)).unwrap();
+        Transfer {
+            from: alice.clone(),
+            to: bob.clone(),
+            amount: rub(20),
+        }
+        .apply(&mut storage)
+        .unwrap();
+        BalanceManager::withdraw(&mut storage, &bob, rub(20)).unwrap();
+        UserManager::remove_user(&mut storage, &bob).unwrap();
+
+        let trial = storage.trial_balance().unwrap();
+        assert!(trial.is_balanced());
+        assert_eq!(trial.totals[&Currency::RUB], (rub(50), rub(50)));
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &cash_account(Currency::RUB)),
+            Some(rub(50))
+        );
+
+        // Проводки каждой транзакции уравновешены: дебет равен кредиту
+        let mut by_tx = BTreeMap::new();
+        for posting in storage.postings() {
+            let debit = match normal_side(&posting.account) {
+                Side::Debit => posting.amount,
+                Side::Credit => posting.amount.checked_neg().unwrap(),
+            };
+            let sum = by_tx.entry(posting.tx).or_insert(rub(0));
+            *sum = sum.checked_add(debit).unwrap();
+        }
+        assert!(by_tx.values().all(|sum| sum.is_zero()));
+    }
+
+    #[test]
+    fn test_internal_accounts_are_reserved() {
+        let mut storage = Storage::new();
+        UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
+        BalanceManager::deposit(&mut storage, &"Alice".to_string(), rub(10)).unwrap();
+        let cash = cash_account(Currency::RUB);
+
+        assert!(matches!(
+            UserManager::add_user(&mut storage, "@fees".to_string()),
+            Err(BankError::ReservedAccount(_))
+        ));
+        assert!(matches!(
+            UserManager::remove_user(&mut storage, &cash),
+            Err(BankError::ReservedAccount(_))
+        ));
+        assert!(
+            Deposit {
+                account: cash.clone(),
+                amount: rub(5),
+            }
+            .apply(&mut storage)
+            .is_err()
+        );
+        assert_eq!(BalanceManager::get_balance(&storage, &cash), Some(rub(10)));
+    }
+
+    #[test]
+    fn test_balances_grouped_by_type() {
+        let mut storage = Storage::new();
+        let alice = "Alice".to_string();
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(100)).unwrap();
+        Booking::fee(alice.clone(), rub(3))
+            .apply(&mut storage)
+            .unwrap();
+        Booking::interest(alice.clone(), rub(1))
+            .apply(&mut storage)
+            .unwrap();
+        // Владельцы вносят капитал в кассу
+        Booking {
+            debit: cash_account(Currency::RUB),
+            credit: equity_account(Currency::RUB),
+            amount: rub(50),
+        }
+        .apply(&mut storage)
+        .unwrap();
+
+        assert_eq!(account_type("@fees:RUB"), Some(AccountType::Income));
+        assert_eq!(account_type("@bonus:RUB"), None);
+        assert_eq!(
+            account_type(&exchange_account(Currency::EUR)),
+            Some(AccountType::Equity)
+        );
+        assert_eq!(account_type(&alice), Some(AccountType::Liability));
+
+        let groups = storage.balances_by_type().unwrap();
+        let totals: Vec<(AccountType, Money)> = groups
+            .iter()
+            .map(|g| (g.kind, g.totals[&Currency::RUB]))
+            .collect();
+        assert_eq!(
+            totals,
+            [
+                (AccountType::Asset, rub(150)),
+                (AccountType::Liability, rub(98)),
+                (AccountType::Equity, rub(50)),
+                (AccountType::Income, rub(3)),
+                (AccountType::Expense, rub(1)),
+            ]
+        );
+        assert!(storage.trial_balance().unwrap().is_balanced());
+    }
+}
@@ -1,4 +1,18 @@
+pub mod account;
+pub mod backend;
 pub mod balance_manager;
+pub mod clock;
+pub mod customer;
+pub mod error;
+pub mod exchange;
+pub mod history;
+pub mod hold;
+pub mod journal;
+pub mod ledger;
+pub mod money;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_backend;
+pub mod statement;
 pub mod storage;
 pub mod transaction;
 pub mod user_manager;
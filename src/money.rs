@@ -0,0 +1,291 @@
+use std::{fmt, str::FromStr};
+
+use crate::error::BankError;
+
+/// ISO 4217 currency code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const RUB: Currency = Currency(*b"RUB");
+    pub const USD: Currency = Currency(*b"USD");
+    pub const EUR: Currency = Currency(*b"EUR");
+    pub const JPY: Currency = Currency(*b"JPY");
+
+    /// Parses a three-letter uppercase code such as "EUR"
+    pub fn new(code: &str) -> Result<Currency, BankError> {
+        match code.as_bytes() {
+            [a, b, c] if code.bytes().all(|ch| ch.is_ascii_uppercase()) => {
+                Ok(Currency([*a, *b, *c]))
+            }
+            _ => Err(BankError::InvalidAmount(format!(
+                "некорректный код валюты '{}'",
+                code
+            ))),
+        }
+    }
+
+    pub fn code(&self) -> &str {
+        // Конструкторы допускают только ASCII, поэтому преобразование не падает
+        std::str::from_utf8(&self.0).unwrap()
+    }
+
+    /// Number of digits after the decimal point (2 for cents, 0 for yen)
+    pub fn minor_digits(&self) -> u32 {
+        match &self.0 {
+            b"JPY" | b"KRW" | b"CLP" | b"ISK" => 0,
+            b"BHD" | b"KWD" | b"OMR" | b"TND" | b"JOD" => 3,
+            _ => 2,
+        }
+    }
+
+    fn minor_per_major(&self) -> i64 {
+        10_i64.pow(self.minor_digits())
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::RUB
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Currency::new(s)
+    }
+}
+
+/// An amount of money in minor units (cents, kopecks) of a single currency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Money {
+    minor: i64,
+    currency: Currency,
+}
+
+impl Money {
+    pub fn new(minor: i64, currency: Currency) -> Money {
+        Money { minor, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Money {
+        Money::new(0, currency)
+    }
+
+    /// Builds an amount from whole units, e.g. 12 EUR = 1200 minor units
+    pub fn from_major(units: i64, currency: Currency) -> Result<Money, BankError> {
+        units
+            .checked_mul(currency.minor_per_major())
+            .map(|minor| Money::new(minor, currency))
+            .ok_or(BankError::Overflow)
+    }
+
+    pub fn minor_units(&self) -> i64 {
+        self.minor
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.minor == 0
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.minor > 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.minor < 0
+    }
+
+    /// Validates an operation amount: every deposit, withdrawal and transfer
+    /// must move a strictly positive sum
+    pub fn ensure_positive(self) -> Result<Money, BankError> {
+        if self.is_positive() {
+            Ok(self)
+        } else {
+            Err(BankError::NonPositiveAmount(self))
+        }
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, BankError> {
+        self.ensure_same_currency(other)?;
+        self.minor
+            .checked_add(other.minor)
+            .map(|minor| Money::new(minor, self.currency))
+            .ok_or(BankError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, BankError> {
+        self.ensure_same_currency(other)?;
+        self.minor
+            .checked_sub(other.minor)
+            .map(|minor| Money::new(minor, self.currency))
+            .ok_or(BankError::Overflow)
+    }
+
+    pub fn checked_neg(self) -> Result<Money, BankError> {
+        self.minor
+            .checked_neg()
+            .map(|minor| Money::new(minor, self.currency))
+            .ok_or(BankError::Overflow)
+    }
+
+    fn ensure_same_currency(&self, other: Money) -> Result<(), BankError> {
+        if self.currency == other.currency {
+            Ok(())
+        } else {
+            Err(BankError::CurrencyMismatch {
+                expected: self.currency,
+                found: other.currency,
+            })
+        }
+    }
+
+    /// Parses an amount without a currency code in the given currency
+    pub fn parse_in(s: &str, currency: Currency) -> Result<Money, BankError> {
+        let invalid = || BankError::InvalidAmount(format!("'{}' не является суммой", s));
+
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let scale = currency.minor_digits() as usize;
+
+        if whole.is_empty()
+            || !whole.bytes().all(|b| b.is_ascii_digit())
+            || !fraction.bytes().all(|b| b.is_ascii_digit())
+            || (digits.contains('.') && fraction.is_empty())
+        {
+            return Err(invalid());
+        }
+        if fraction.len() > scale {
+            return Err(BankError::InvalidAmount(format!(
+                "у {} не больше {} знаков после запятой",
+                currency, scale
+            )));
+        }
+
+        let whole: i64 = whole.parse().map_err(|_| BankError::Overflow)?;
+        let fraction: i64 = if fraction.is_empty() {
+            0
+        } else {
+            format!("{:0<width$}", fraction, width = scale)
+                .parse()
+                .map_err(|_| invalid())?
+        };
+
+        let minor = whole
+            .checked_mul(currency.minor_per_major())
+            .and_then(|m| m.checked_add(fraction))
+            .ok_or(BankError::Overflow)?;
+        Ok(Money::new(if negative { -minor } else { minor }, currency))
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let per_major = self.currency.minor_per_major() as u64;
+        let abs = self.minor.unsigned_abs();
+        let sign = if self.minor < 0 { "-" } else { "" };
+
+        match self.currency.minor_digits() {
+            0 => write!(f, "{}{} {}", sign, abs, self.currency),
+            digits => write!(
+                f,
+                "{}{}.{:0width$} {}",
+                sign,
+                abs / per_major,
+                abs % per_major,
+                self.currency,
+                width = digits as usize
+            ),
+        }
+    }
+}
+
+/// Parses "12.34 EUR"; without a code the default currency is assumed
+impl FromStr for Money {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let amount = parts
+            .next()
+            .ok_or_else(|| BankError::InvalidAmount("пустая строка вместо суммы".to_string()))?;
+        let currency = match parts.next() {
+            Some(code) => Currency::new(code)?,
+            None => Currency::default(),
+        };
+        if parts.next().is_some() {
+            return Err(BankError::InvalidAmount(format!(
+                "'{}' не является суммой",
+                s
+            )));
+        }
+        Money::parse_in(amount, currency)
+    }
+}
+
+/// Whole rubles, used throughout the tests
+#[cfg(test)]
+pub(crate) fn rub(units: i64) -> Money {
+    Money::from_major(units, Currency::RUB).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        let m: Money = "12.34 EUR".parse().unwrap();
+        assert_eq!(m, Money::new(1234, Currency::EUR));
+        assert_eq!(m.to_string(), "12.34 EUR");
+
+        assert_eq!("100".parse::<Money>().unwrap(), rub(100));
+        assert_eq!("0.5 USD".parse::<Money>().unwrap().to_string(), "0.50 USD");
+        assert_eq!(
+            "-0.05 RUB".parse::<Money>().unwrap().to_string(),
+            "-0.05 RUB"
+        );
+        assert_eq!("1500 JPY".parse::<Money>().unwrap().minor_units(), 1500);
+
+        assert!(matches!(
+            "1.234 EUR".parse::<Money>(),
+            Err(BankError::InvalidAmount(_))
+        ));
+        assert!("12,5".parse::<Money>().is_err());
+        assert!("12 eur".parse::<Money>().is_err());
+        assert!("99999999999999999999".parse::<Money>().is_err());
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        assert_eq!(rub(2).checked_add(rub(3)).unwrap(), rub(5));
+        assert_eq!(rub(2).checked_sub(rub(3)).unwrap(), rub(-1));
+
+        assert!(matches!(
+            Money::new(i64::MAX, Currency::RUB).checked_add(Money::new(1, Currency::RUB)),
+            Err(BankError::Overflow)
+        ));
+        assert!(matches!(
+            rub(1).checked_add(Money::new(1, Currency::EUR)),
+            Err(BankError::CurrencyMismatch {
+                expected: Currency::RUB,
+                found: Currency::EUR
+            })
+        ));
+    }
+}
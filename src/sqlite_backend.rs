@@ -0,0 +1,748 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use rusqlite::{Connection, params};
+
+use crate::{
+    account::{Account, AccountLimits},
+    backend::{NEXT_ACCOUNT_ID, NEXT_HOLD_ID, StorageBackend},
+    clock::Timestamp,
+    customer::{Customer, CustomerId},
+    error::BankError,
+    hold::{Hold, HoldId},
+    journal::{Journal, JournalRecord},
+    money::{Currency, Money},
+    statement::Posting,
+    storage::{LoadMode, Name, Snapshot, Storage},
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS accounts (
+        name      TEXT PRIMARY KEY,
+        balance   INTEGER NOT NULL,
+        currency  TEXT NOT NULL,
+        id        INTEGER NOT NULL,
+        owners    TEXT NOT NULL,
+        opened_at INTEGER NOT NULL,
+        status    TEXT NOT NULL,
+        limits    TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transactions (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        record     TEXT NOT NULL,
+        applied_at INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS customers (
+        id    INTEGER PRIMARY KEY,
+        since INTEGER NOT NULL,
+        name  TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS default_limits (
+        currency TEXT PRIMARY KEY,
+        limits   TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS holds (
+        id         INTEGER PRIMARY KEY,
+        account    TEXT NOT NULL,
+        amount     INTEGER NOT NULL,
+        currency   TEXT NOT NULL,
+        placed_at  INTEGER NOT NULL,
+        expires_at INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS postings (
+        id      INTEGER PRIMARY KEY AUTOINCREMENT,
+        posting TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS meta (
+        name  TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+";
+
+/// `user_version` of a database that already holds the bank. Every commit
+/// sets it, so a database left empty by a failed import is imported again.
+const BANK_VERSION: i64 = 1;
+
+/// Stores accounts and applied transactions in an embedded SQLite database.
+///
+/// Balances are cached in memory; changed rows, recorded transactions and
+/// postings are written together inside one database transaction on
+/// `commit`, so both legs of a `Transfer` become durable together or not at all.
+pub struct SqliteBackend {
+    conn: Connection,
+    accounts: HashMap<Name, Account>,
+    dirty: HashSet<Name>,
+    customers: BTreeMap<CustomerId, Customer>,
+    dirty_customers: HashSet<CustomerId>,
+    removed_customers: HashSet<CustomerId>,
+    default_limits: BTreeMap<Currency, AccountLimits>,
+    dirty_limits: HashSet<Currency>,
+    holds: BTreeMap<HoldId, Hold>,
+    dirty_holds: HashSet<HoldId>,
+    removed_holds: HashSet<HoldId>,
+    removed: HashSet<Name>,
+    pending: Vec<JournalRecord>,
+    pending_postings: Vec<Posting>,
+    counters: BTreeMap<String, u64>,
+    dirty_counters: HashSet<String>,
+}
+
+impl SqliteBackend {
+    /// Opens (or creates) the database file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, BankError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Opens the database. A database that does not hold a bank yet gets the
+    /// CSV snapshot `csv` together with the records of its `journal`, all in
+    /// one database transaction.
+    pub fn open_or_migrate<P: AsRef<Path>>(
+        path: P,
+        csv: &str,
+        journal: &str,
+    ) -> Result<Self, BankError> {
+        let mut backend = Self::open(path)?;
+        let version: i64 = backend
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < BANK_VERSION && Path::new(csv).exists() {
+            backend.import(csv, journal)?;
+        }
+        Ok(backend)
+    }
+
+    fn import(&mut self, csv: &str, journal: &str) -> Result<(), BankError> {
+        let mut storage = Storage::try_load_data(csv, LoadMode::Strict)?.storage;
+        if Path::new(journal).exists() {
+            Journal::open(journal, &mut storage)?;
+        }
+        for account in storage.get_all() {
+            self.put(account.clone());
+        }
+        for customer in storage.customers() {
+            self.put_customer(customer.clone());
+        }
+        for (currency, limits) in storage.default_limits() {
+            self.put_default_limits(currency, limits);
+        }
+        for hold in storage.holds() {
+            self.put_hold(hold.clone());
+        }
+        for posting in storage.postings() {
+            self.record_posting(posting);
+        }
+
+        // Транзакции из снимка и журнала переносятся, чтобы их можно было отменить
+        for entry in storage.history().iter() {
+            self.record(&entry.posted())?;
+        }
+        self.commit(&storage.snapshot())
+    }
+
+    /// Remembers a counter so it is persisted on the next `commit`
+    fn put_counter(&mut self, name: &str, value: u64) {
+        if self.counters.get(name) != Some(&value) {
+            self.counters.insert(name.to_string(), value);
+            self.dirty_counters.insert(name.to_string());
+        }
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, BankError> {
+        conn.execute_batch(SCHEMA)?;
+        let mut accounts = HashMap::new();
+        {
+            let mut stmt = conn.prepare(
+                "SELECT name, balance, currency, id, owners, opened_at, status, limits
+                 FROM accounts",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, i64>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, String>(7)?,
+                ))
+            })?;
+            for row in rows {
+                let (name, minor, currency, id, owners, opened_at, status, limits) = row?;
+                let currency = Currency::new(&currency)?;
+                // Счета без владельцев получат их в `Storage::with_backend`
+                let owners = owners.split(';').filter_map(|id| id.parse().ok()).collect();
+                let mut account = Account::new(
+                    name.clone(),
+                    owners,
+                    currency,
+                    Timestamp::from_unix(opened_at),
+                );
+                account.id = id as u64;
+                account.balance = Money::new(minor, currency);
+                account.status = status.parse()?;
+                account.limits = limits.parse()?;
+                accounts.insert(name, account);
+            }
+        }
+
+        let mut customers = BTreeMap::new();
+        {
+            let mut stmt = conn.prepare("SELECT id, since, name FROM customers")?;
+            let rows = stmt.query_map([], |row| {
+                Ok(Customer {
+                    id: row.get::<_, i64>(0)? as u64,
+                    since: Timestamp::from_unix(row.get(1)?),
+                    name: row.get(2)?,
+                })
+            })?;
+            for row in rows {
+                let customer = row?;
+                customers.insert(customer.id, customer);
+            }
+        }
+
+        let mut default_limits = BTreeMap::new();
+        {
+            let mut stmt = conn.prepare("SELECT currency, limits FROM default_limits")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            for row in rows {
+                let (currency, limits) = row?;
+                default_limits.insert(Currency::new(&currency)?, limits.parse()?);
+            }
+        }
+
+        let mut holds = BTreeMap::new();
+        {
+            let mut stmt = conn.prepare(
+                "SELECT id, account, amount, currency, placed_at, expires_at FROM holds",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, Option<i64>>(5)?,
+                ))
+            })?;
+            for row in rows {
+                let (id, account, amount, currency, placed_at, expires_at) = row?;
+                let hold = Hold {
+                    id: id as u64,
+                    account,
+                    amount: Money::new(amount, Currency::new(&currency)?),
+                    placed_at: Timestamp::from_unix(placed_at),
+                    expires_at: expires_at.map(Timestamp::from_unix),
+                };
+                holds.insert(hold.id, hold);
+            }
+        }
+
+        let mut counters = BTreeMap::new();
+        {
+            let mut stmt = conn.prepare("SELECT name, value FROM meta")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?;
+            for row in rows {
+                let (name, value) = row?;
+                counters.insert(name, value as u64);
+            }
+        }
+
+        Ok(SqliteBackend {
+            conn,
+            accounts,
+            dirty: HashSet::new(),
+            customers,
+            dirty_customers: HashSet::new(),
+            removed_customers: HashSet::new(),
+            default_limits,
+            dirty_limits: HashSet::new(),
+            holds,
+            dirty_holds: HashSet::new(),
+            removed_holds: HashSet::new(),
+            removed: HashSet::new(),
+            pending: Vec::new(),
+            pending_postings: Vec::new(),
+            counters,
+            dirty_counters: HashSet::new(),
+        })
+    }
+
+    /// Returns every committed transaction record, oldest first
+    pub fn transactions(&self) -> Result<Vec<JournalRecord>, BankError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT record FROM transactions ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            records.push(row?.parse().map_err(BankError::Parse)?);
+        }
+        Ok(records)
+    }
+
+    /// Returns every committed posting, oldest first
+    pub fn postings(&self) -> Result<Vec<Posting>, BankError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT posting FROM postings ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+
+        let mut postings = Vec::new();
+        for row in rows {
+            postings.push(row?.parse()?);
+        }
+        Ok(postings)
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn get(&self, name: &Name) -> Option<&Account> {
+        self.accounts.get(name)
+    }
+
+    fn put(&mut self, account: Account) {
+        self.removed.remove(&account.name);
+        self.dirty.insert(account.name.clone());
+        self.accounts.insert(account.name.clone(), account);
+    }
+
+    fn remove(&mut self, name: &Name) -> Option<Account> {
+        let account = self.accounts.remove(name)?;
+        self.dirty.remove(name);
+        self.removed.insert(name.clone());
+        Some(account)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(self.accounts.values())
+    }
+
+    fn get_customer(&self, id: CustomerId) -> Option<&Customer> {
+        self.customers.get(&id)
+    }
+
+    fn put_customer(&mut self, customer: Customer) {
+        self.removed_customers.remove(&customer.id);
+        self.dirty_customers.insert(customer.id);
+        self.customers.insert(customer.id, customer);
+    }
+
+    fn remove_customer(&mut self, id: CustomerId) -> Option<Customer> {
+        let customer = self.customers.remove(&id)?;
+        self.dirty_customers.remove(&id);
+        self.removed_customers.insert(id);
+        Some(customer)
+    }
+
+    fn customers(&self) -> Box<dyn Iterator<Item = &Customer> + '_> {
+        Box::new(self.customers.values())
+    }
+
+    fn default_limits(&self) -> Box<dyn Iterator<Item = (Currency, AccountLimits)> + '_> {
+        Box::new(self.default_limits.iter().map(|(c, l)| (*c, *l)))
+    }
+
+    fn put_default_limits(&mut self, currency: Currency, limits: AccountLimits) {
+        self.dirty_limits.insert(currency);
+        self.default_limits.insert(currency, limits);
+    }
+
+    fn get_hold(&self, id: HoldId) -> Option<&Hold> {
+        self.holds.get(&id)
+    }
+
+    fn put_hold(&mut self, hold: Hold) {
+        self.removed_holds.remove(&hold.id);
+        self.dirty_holds.insert(hold.id);
+        self.holds.insert(hold.id, hold);
+    }
+
+    fn remove_hold(&mut self, id: HoldId) -> Option<Hold> {
+        let hold = self.holds.remove(&id)?;
+        self.dirty_holds.remove(&id);
+        self.removed_holds.insert(id);
+        Some(hold)
+    }
+
+    fn holds(&self) -> Box<dyn Iterator<Item = &Hold> + '_> {
+        Box::new(self.holds.values())
+    }
+
+    fn record(&mut self, record: &JournalRecord) -> Result<(), BankError> {
+        self.pending.push(record.clone());
+        Ok(())
+    }
+
+    fn record_posting(&mut self, posting: &Posting) {
+        self.pending_postings.push(posting.clone());
+    }
+
+    fn discard(&mut self) {
+        self.pending.clear();
+        self.pending_postings.clear();
+    }
+
+    fn counter(&self, name: &str) -> Option<u64> {
+        self.counters.get(name).copied()
+    }
+
+    fn commit(&mut self, snapshot: &Snapshot<'_>) -> Result<(), BankError> {
+        self.put_counter(NEXT_ACCOUNT_ID, snapshot.next_account_id);
+        self.put_counter(NEXT_HOLD_ID, snapshot.next_hold_id);
+        // Время берётся из часов банка, как и у квитанций и проводок
+        let applied_at = snapshot.committed_at.unix();
+
+        // Если любая операция упадёт, транзакция откатится при drop
+        let tx = self.conn.transaction()?;
+        for name in &self.dirty {
+            let account = &self.accounts[name];
+            tx.execute(
+                "INSERT INTO accounts
+                     (name, balance, currency, id, owners, opened_at, status, limits)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                 ON CONFLICT(name) DO UPDATE
+                 SET balance = excluded.balance, currency = excluded.currency,
+                     id = excluded.id, owners = excluded.owners,
+                     opened_at = excluded.opened_at, status = excluded.status,
+                     limits = excluded.limits",
+                params![
+                    name,
+                    account.balance.minor_units(),
+                    account.currency().code(),
+                    account.id as i64,
+                    account
+                        .owners
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(";"),
+                    account.opened_at.unix(),
+                    account.status.to_string(),
+                    account.limits.to_string()
+                ],
+            )?;
+        }
+        for id in &self.dirty_customers {
+            let customer = &self.customers[id];
+            tx.execute(
+                "INSERT INTO customers (id, since, name) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(id) DO UPDATE SET since = excluded.since, name = excluded.name",
+                params![*id as i64, customer.since.unix(), customer.name],
+            )?;
+        }
+        for id in &self.removed_customers {
+            tx.execute("DELETE FROM customers WHERE id = ?1", params![*id as i64])?;
+        }
+        for currency in &self.dirty_limits {
+            tx.execute(
+                "INSERT INTO default_limits (currency, limits) VALUES (?1, ?2)
+                 ON CONFLICT(currency) DO UPDATE SET limits = excluded.limits",
+                params![currency.code(), self.default_limits[currency].to_string()],
+            )?;
+        }
+        for id in &self.dirty_holds {
+            let hold = &self.holds[id];
+            tx.execute(
+                "INSERT OR REPLACE INTO holds
+                     (id, account, amount, currency, placed_at, expires_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    *id as i64,
+                    hold.account,
+                    hold.amount.minor_units(),
+                    hold.amount.currency().code(),
+                    hold.placed_at.unix(),
+                    hold.expires_at.map(|at| at.unix())
+                ],
+            )?;
+        }
+        for id in &self.removed_holds {
+            tx.execute("DELETE FROM holds WHERE id = ?1", params![*id as i64])?;
+        }
+        for name in &self.removed {
+            tx.execute("DELETE FROM accounts WHERE name = ?1", params![name])?;
+        }
+        for record in &self.pending {
+            tx.execute(
+                "INSERT INTO transactions (record, applied_at) VALUES (?1, ?2)",
+                params![record.to_string(), applied_at],
+            )?;
+        }
+        for posting in &self.pending_postings {
+            tx.execute(
+                "INSERT INTO postings (posting) VALUES (?1)",
+                params![posting.to_string()],
+            )?;
+        }
+        for name in &self.dirty_counters {
+            tx.execute(
+                "INSERT INTO meta (name, value) VALUES (?1, ?2)
+                 ON CONFLICT(name) DO UPDATE SET value = excluded.value",
+                params![name, self.counters[name] as i64],
+            )?;
+        }
+        tx.execute_batch(&format!("PRAGMA user_version = {}", BANK_VERSION))?;
+        tx.commit()?;
+
+        self.dirty.clear();
+        self.dirty_customers.clear();
+        self.removed_customers.clear();
+        self.dirty_limits.clear();
+        self.dirty_holds.clear();
+        self.removed_holds.clear();
+        self.pending_postings.clear();
+        self.removed.clear();
+        self.pending.clear();
+        self.dirty_counters.clear();
+        Ok(())
+    }
+}
+
+impl From<rusqlite::Error> for BankError {
+    fn from(e: rusqlite::Error) -> Self {
+        BankError::Database(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        balance_manager::BalanceManager,
+        clock::{FixedClock, Timestamp},
+        money::rub,
+        transaction::{Deposit, Transaction, Transfer},
+        user_manager::UserManager,
+    };
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("bank-{}-{}", name, std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_transfer_commits_both_legs() {
+        let db = temp_path("sqlite-transfer.db");
+        {
+            let mut storage = Storage::with_backend(SqliteBackend::open(&db).unwrap());
+            UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
+            UserManager::add_user(&mut storage, "Bob".to_string()).unwrap();
+            BalanceManager::deposit(&mut storage, &"Alice".to_string(), rub(100)).unwrap();
+            storage.commit().unwrap();
+
+            let record = JournalRecord::Transfer {
+                from: "Alice".to_string(),
+                to: "Bob".to_string(),
+                amount: rub(30),
+            };
+            Transfer {
+                from: "Alice".to_string(),
+                to: "Bob".to_string(),
+                amount: rub(30),
+            }
+            .apply(&mut storage)
+            .unwrap();
+            storage.record(&record).unwrap();
+            storage.commit().unwrap();
+        }
+
+        let backend = SqliteBackend::open(&db).unwrap();
+        assert_eq!(backend.transactions().unwrap().len(), 1);
+        let storage = Storage::with_backend(backend);
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(70))
+        );
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Bob".to_string()),
+            Some(rub(30))
+        );
+
+        let _ = fs::remove_file(&db);
+    }
+
+    #[test]
+    fn test_commit_time_follows_bank_clock() {
+        let db = temp_path("sqlite-clock.db");
+        let at: Timestamp = "2024-05-01 10:00:00".parse().unwrap();
+        let mut storage = Storage::with_backend(SqliteBackend::open(&db).unwrap());
+        storage.set_clock(FixedClock(at));
+        UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
+        let deposit = Deposit {
+            account: "Alice".to_string(),
+            amount: rub(10),
+        };
+        let receipt = storage.execute(&deposit, None).unwrap();
+        storage
+            .record(&JournalRecord::Posted {
+                receipt: receipt.clone(),
+                record: Box::new(deposit.journal_record()),
+            })
+            .unwrap();
+        storage.commit().unwrap();
+
+        let conn = Connection::open(&db).unwrap();
+        let applied_at: i64 = conn
+            .query_row("SELECT applied_at FROM transactions", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(applied_at, at.unix());
+        assert_eq!(receipt.applied_at, at);
+
+        let _ = fs::remove_file(&db);
+    }
+
+    #[test]
+    fn test_uncommitted_changes_are_not_persisted() {
+        let db = temp_path("sqlite-uncommitted.db");
+        {
+            let mut storage = Storage::with_backend(SqliteBackend::open(&db).unwrap());
+            UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
+        }
+
+        let storage = Storage::with_backend(SqliteBackend::open(&db).unwrap());
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            None
+        );
+
+        let _ = fs::remove_file(&db);
+    }
+
+    #[test]
+    fn test_migrates_csv_on_first_run() {
+        let db = temp_path("sqlite-migrate.db");
+        let csv = temp_path("sqlite-migrate.csv");
+        let journal = temp_path("sqlite-migrate.journal");
+        fs::write(&csv, "John,100\nAlice,5\nBob,\n").unwrap();
+
+        // Неудачный импорт не оставляет пустую базу, которую больше не импортируют
+        assert!(SqliteBackend::open_or_migrate(&db, &csv, &journal).is_err());
+        fs::write(&csv, "John,100\nAlice,5\n").unwrap();
+        let storage =
+            Storage::with_backend(SqliteBackend::open_or_migrate(&db, &csv, &journal).unwrap());
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"John".to_string()),
+            Some(rub(100))
+        );
+        drop(storage);
+
+        // Повторный запуск не импортирует CSV заново
+        fs::write(&csv, "John,1\n").unwrap();
+        let storage =
+            Storage::with_backend(SqliteBackend::open_or_migrate(&db, &csv, &journal).unwrap());
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"John".to_string()),
+            Some(rub(100))
+        );
+
+        let _ = fs::remove_file(&db);
+        let _ = fs::remove_file(&csv);
+    }
+
+    #[test]
+    fn test_migration_keeps_journal() {
+        let db = temp_path("sqlite-migrate-journal.db");
+        let csv = temp_path("sqlite-migrate-journal.csv");
+        let journal_path = temp_path("sqlite-migrate-journal.journal");
+        fs::write(&csv, "John,100\nAlice,0\n").unwrap();
+
+        // Перевод есть только в журнале, но не в снимке
+        let mut storage = Storage::try_load_data(&csv, LoadMode::Strict)
+            .unwrap()
+            .storage;
+        let mut journal = Journal::open(&journal_path, &mut storage).unwrap();
+        let record = JournalRecord::Transfer {
+            from: "John".to_string(),
+            to: "Alice".to_string(),
+            amount: rub(10),
+        };
+        record.apply(&mut storage).unwrap();
+        journal.append(&record).unwrap();
+
+        let backend = SqliteBackend::open_or_migrate(&db, &csv, &journal_path).unwrap();
+        let migrated = Storage::with_backend(backend);
+        assert_eq!(
+            BalanceManager::get_balance(&migrated, &"Alice".to_string()),
+            Some(rub(10))
+        );
+
+        let _ = fs::remove_file(&db);
+        let _ = fs::remove_file(&csv);
+        let _ = fs::remove_file(&journal_path);
+    }
+
+    #[test]
+    fn test_keeps_customers_of_joint_accounts() {
+        let db = temp_path("sqlite-customers.db");
+        let john = {
+            let mut storage = Storage::with_backend(SqliteBackend::open(&db).unwrap());
+            UserManager::add_user(&mut storage, "John".to_string()).unwrap();
+            let alice = UserManager::add_customer(&mut storage, "Alice".to_string()).unwrap();
+            UserManager::open_account(
+                &mut storage,
+                "family".to_string(),
+                &[1, alice],
+                Currency::RUB,
+            )
+            .unwrap();
+            storage.commit().unwrap();
+            storage.get_account(&"John".to_string()).unwrap().clone()
+        };
+
+        let storage = Storage::with_backend(SqliteBackend::open(&db).unwrap());
+        assert_eq!(storage.get_account(&"John".to_string()), Some(&john));
+        assert_eq!(
+            storage.get_account(&"family".to_string()).unwrap().owners,
+            vec![1, 2]
+        );
+        assert_eq!(storage.get_customer(2).unwrap().name, "Alice");
+
+        let _ = fs::remove_file(&db);
+    }
+
+    #[test]
+    fn test_ids_of_removed_accounts_are_not_reused() {
+        let db = temp_path("sqlite-ids.db");
+        let john = "John".to_string();
+        let id = {
+            let mut storage = Storage::with_backend(SqliteBackend::open(&db).unwrap());
+            UserManager::add_user(&mut storage, john.clone()).unwrap();
+            let id =
+                UserManager::open_account(&mut storage, "john-2".to_string(), &[1], Currency::RUB)
+                    .unwrap();
+            UserManager::remove_user(&mut storage, &"john-2".to_string()).unwrap();
+            BalanceManager::deposit(&mut storage, &john, rub(10)).unwrap();
+            let hold = BalanceManager::authorize(&mut storage, &john, rub(5), None).unwrap();
+            BalanceManager::release(&mut storage, hold).unwrap();
+            storage.commit().unwrap();
+            id
+        };
+
+        let mut storage = Storage::with_backend(SqliteBackend::open(&db).unwrap());
+        let next =
+            UserManager::open_account(&mut storage, "john-3".to_string(), &[1], Currency::RUB)
+                .unwrap();
+        // Номера удалённого счёта и снятой блокировки хранятся в базе
+        assert!(next > id);
+        assert_eq!(
+            BalanceManager::authorize(&mut storage, &john, rub(5), None).unwrap(),
+            2
+        );
+
+        let _ = fs::remove_file(&db);
+    }
+}
@@ -0,0 +1,254 @@
+use std::{fmt, str::FromStr};
+
+use crate::{clock::Timestamp, error::BankError, history::TxId, money::Money, storage::Name};
+
+/// What caused a posting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostingKind {
+    /// The account was created
+    Open,
+    /// Money moved in or out of the account
+    Movement,
+    /// The account was removed; the amount writes off its final balance
+    Close,
+}
+
+/// A single change of one account's balance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Posting {
+    pub at: Timestamp,
+    pub kind: PostingKind,
+    pub account: Name,
+    /// Signed change: positive when the balance grows on the account's
+    /// normal side (see `ledger::normal_side`), negative when it shrinks
+    pub amount: Money,
+    /// The transaction that caused the change, if it went through one
+    pub tx: Option<TxId>,
+    pub memo: Option<String>,
+}
+
+/// Stored as `unix,tx,kind,account,amount,memo`; memo is last so it may contain commas
+impl fmt::Display for Posting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            PostingKind::Open => "open",
+            PostingKind::Movement => "move",
+            PostingKind::Close => "close",
+        };
+        let tx = self.tx.map(|id| id.to_string()).unwrap_or_default();
+        write!(
+            f,
+            "{},{},{},{},{},{}",
+            self.at.unix(),
+            tx,
+            kind,
+            self.account,
+            self.amount,
+            self.memo.as_deref().unwrap_or("")
+        )
+    }
+}
+
+impl FromStr for Posting {
+    type Err = BankError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BankError::Parse(format!("некорректная проводка '{}'", s));
+        let [at, tx, kind, account, amount, memo] = s.splitn(6, ',').collect::<Vec<_>>()[..] else {
+            return Err(invalid());
+        };
+
+        Ok(Posting {
+            at: Timestamp::from_unix(at.parse().map_err(|_| invalid())?),
+            kind: match kind {
+                "open" => PostingKind::Open,
+                "move" => PostingKind::Movement,
+                "close" => PostingKind::Close,
+                _ => return Err(invalid()),
+            },
+            account: account.to_string(),
+            amount: amount.parse()?,
+            tx: match tx {
+                "" => None,
+                id => Some(id.parse().map_err(|_| invalid())?),
+            },
+            memo: Some(memo.to_string()).filter(|m| !m.is_empty()),
+        })
+    }
+}
+
+/// A posting together with the account balance right after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementLine {
+    pub posting: Posting,
+    pub balance: Money,
+}
+
+/// Account activity over a period `[from, to)`; a missing bound is open-ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    pub account: Name,
+    pub from: Option<Timestamp>,
+    pub to: Option<Timestamp>,
+    pub opening: Money,
+    pub lines: Vec<StatementLine>,
+    pub closing: Money,
+}
+
+impl Statement {
+    /// Builds a statement from the account's postings (in the order they were
+    /// made) and its balance now; a removed account has a zero balance.
+    ///
+    /// The opening balance is worked out backwards from the current one, so
+    /// balances that predate the recorded history are still accounted for.
+    pub(crate) fn build<'a>(
+        account: &Name,
+        current: Money,
+        postings: impl IntoIterator<Item = &'a Posting>,
+        from: Option<Timestamp>,
+        to: Option<Timestamp>,
+    ) -> Result<Statement, BankError> {
+        let postings: Vec<&Posting> = postings
+            .into_iter()
+            .filter(|p| &p.account == account)
+            .collect();
+        let before = |p: &&Posting| from.is_some_and(|from| p.at < from);
+
+        let mut opening = current;
+        // Нулевые проводки (открытие счёта) валюты счёта не знают
+        for posting in postings
+            .iter()
+            .filter(|p| !before(p) && !p.amount.is_zero())
+        {
+            opening = opening.checked_sub(posting.amount)?;
+        }
+
+        let mut balance = opening;
+        let mut lines = Vec::new();
+        for posting in postings
+            .into_iter()
+            .filter(|p| !before(p) && to.is_none_or(|to| p.at < to))
+        {
+            if !posting.amount.is_zero() {
+                balance = balance.checked_add(posting.amount)?;
+            }
+            lines.push(StatementLine {
+                posting: posting.clone(),
+                balance,
+            });
+        }
+
+        Ok(Statement {
+            account: account.clone(),
+            from,
+            to,
+            opening,
+            lines,
+            closing: balance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::FixedClock,
+        money::rub,
+        storage::Storage,
+        transaction::{Batch, Deposit, Transaction, Transfer, Withdraw},
+        user_manager::UserManager,
+    };
+
+    fn at(date: &str) -> Timestamp {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn test_posting_round_trip() {
+        let posting = Posting {
+            at: at("2024-05-01 10:00:00"),
+            kind: PostingKind::Movement,
+            account: "Alice".to_string(),
+            amount: rub(-5),
+            tx: Some(12),
+            memo: Some("кофе, булочка".to_string()),
+        };
+        assert_eq!(posting.to_string().parse::<Posting>().unwrap(), posting);
+    }
+
+    #[test]
+    fn test_statement_for_period() {
+        let mut storage = Storage::new();
+        storage.set_clock(FixedClock(at("2024-04-30")));
+        UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
+        UserManager::add_user(&mut storage, "Bob".to_string()).unwrap();
+        Deposit {
+            account: "Alice".to_string(),
+            amount: rub(100),
+        }
+        .apply(&mut storage)
+        .unwrap();
+
+        storage.set_clock(FixedClock(at("2024-05-10")));
+        Transfer {
+            from: "Alice".to_string(),
+            to: "Bob".to_string(),
+            amount: rub(30),
+        }
+        .apply_with_memo(&mut storage, "долг")
+        .unwrap();
+        // Откаченный пакет не оставляет проводок
+        let failed = Batch::new(vec![
+            Box::new(Deposit {
+                account: "Alice".to_string(),
+                amount: rub(1),
+            }),
+            Box::new(Withdraw {
+                account: "Alice".to_string(),
+                amount: rub(1000),
+            }),
+        ]);
+        assert!(failed.apply(&mut storage).is_err());
+
+        storage.set_clock(FixedClock(at("2024-06-02")));
+        Withdraw {
+            account: "Alice".to_string(),
+            amount: rub(20),
+        }
+        .apply(&mut storage)
+        .unwrap();
+
+        let statement = storage
+            .statement(
+                &"Alice".to_string(),
+                Some(at("2024-05-01")),
+                Some(at("2024-06-01")),
+            )
+            .unwrap();
+        assert_eq!(statement.opening, rub(100));
+        assert_eq!(statement.lines.len(), 1);
+        assert_eq!(statement.lines[0].posting.amount, rub(-30));
+        assert_eq!(statement.lines[0].posting.memo.as_deref(), Some("долг"));
+        assert_eq!(statement.closing, rub(70));
+
+        // Без границ выписка охватывает всю историю, включая открытие счёта
+        let full = storage.statement(&"Alice".to_string(), None, None).unwrap();
+        assert_eq!(full.opening, rub(0));
+        assert_eq!(full.lines[0].posting.kind, PostingKind::Open);
+        assert_eq!(full.closing, rub(50));
+
+        Withdraw {
+            account: "Alice".to_string(),
+            amount: rub(50),
+        }
+        .apply(&mut storage)
+        .unwrap();
+        UserManager::remove_user(&mut storage, &"Alice".to_string()).unwrap();
+        let closed = storage
+            .statement(&"Alice".to_string(), Some(at("2024-06-01")), None)
+            .unwrap();
+        assert_eq!(closed.opening, rub(70));
+        assert_eq!(closed.closing, rub(0));
+    }
+}
@@ -1,125 +1,1543 @@
 use std::{
-    collections::{HashMap, hash_map},
+    collections::BTreeMap,
     fs::{self, File},
-    io::{self, BufRead},
+    io::{self, BufRead, Write},
     path::Path,
 };
 
-use crate::{balance_manager::BalanceManager, user_manager::UserManager};
+use crate::{
+    account::{Account, AccountId, AccountLimits, AccountStatus, CSV_HEADER, LimitKind},
+    backend::{CsvBackend, MemoryBackend, NEXT_ACCOUNT_ID, NEXT_HOLD_ID, StorageBackend},
+    clock::{Clock, SECONDS_PER_DAY, SystemClock, Timestamp},
+    customer::{Customer, CustomerId},
+    error::{AccountSide, BankError, LineError},
+    history::{Receipt, TxId, TxLog},
+    hold::{Hold, HoldId},
+    journal::JournalRecord,
+    ledger::{AccountGroup, TrialBalance, cash_account, is_internal},
+    money::{Currency, Money},
+    statement::{Posting, PostingKind, Statement},
+    transaction::{Transaction, Transfer},
+    user_manager::UserManager,
+};
 
 pub type Name = String;
-type Balance = i64;
+pub type Balance = Money;
 
 pub struct Storage {
-    pub(crate) accounts: HashMap<Name, Balance>,
+    backend: Box<dyn StorageBackend>,
+    /// Прежние значения изменённых счетов, клиентов, блокировок и лимитов,
+    /// пока открыта точка сохранения
+    undo: Vec<Undo>,
+    savepoints: usize,
+    /// Номер последней записи журнала, уже учтённой в снимке
+    pub(crate) journal_seq: u64,
+    /// Применённые транзакции по номерам
+    history: TxLog,
+    /// Источник времени для квитанций
+    clock: Box<dyn Clock>,
+    /// Все изменения балансов по порядку
+    postings: Vec<Posting>,
+    /// Сколько проводок уже передано хранилищу; остальные ещё могут быть откачены
+    recorded_postings: usize,
+    /// Квитанция транзакции, которая сейчас меняет балансы
+    current_tx: Option<Receipt>,
+    /// Исходное время воспроизводимой записи, которое заменяет показания часов
+    replay_at: Option<Timestamp>,
+    /// Идёт отмена транзакции: она возвращает деньги на место и не
+    /// расходует лимиты, а заморозка счёта ей не мешает
+    compensating: bool,
+    /// Номер, который получит следующий открытый счёт
+    pub(crate) next_account_id: AccountId,
+    /// Номер, который получит следующий клиент
+    next_customer_id: CustomerId,
+    /// Номер, который получит следующая блокировка
+    pub(crate) next_hold_id: HoldId,
+}
+
+/// Прежнее значение, которое восстанавливает `Storage::rollback_to`
+enum Undo {
+    Account(Name, Option<Account>),
+    Hold(HoldId, Option<Hold>),
+    Customer(CustomerId, Option<Customer>),
+    DefaultLimits(Currency, Option<AccountLimits>),
+}
+
+/// Состояние, к которому возвращает `Storage::rollback_to`
+pub(crate) struct Savepoint {
+    undo: usize,
+    postings: usize,
+    next_tx_id: TxId,
+    next_account_id: AccountId,
+    next_customer_id: CustomerId,
+    next_hold_id: HoldId,
 }
 
 impl Storage {
-    /// Создаёт новый пустой банк
+    /// Создаёт новый пустой банк, хранящий данные в памяти
     pub fn new() -> Self {
-        Storage {
-            accounts: HashMap::new(),
+        Self::with_backend(MemoryBackend::new())
+    }
+
+    /// Создаёт банк поверх произвольного хранилища
+    pub fn with_backend(backend: impl StorageBackend + 'static) -> Self {
+        let mut storage = Storage {
+            backend: Box::new(backend),
+            undo: Vec::new(),
+            savepoints: 0,
+            journal_seq: 0,
+            history: TxLog::new(),
+            clock: Box::new(SystemClock),
+            postings: Vec::new(),
+            recorded_postings: 0,
+            current_tx: None,
+            replay_at: None,
+            compensating: false,
+            next_account_id: 1,
+            next_customer_id: 1,
+            next_hold_id: 1,
+        };
+        // Номера удалённых счетов и снятых блокировок не выдаются повторно
+        let counter = |name| storage.backend.counter(name).unwrap_or(1);
+        let (next_account_id, next_hold_id) = (counter(NEXT_ACCOUNT_ID), counter(NEXT_HOLD_ID));
+        storage.next_account_id = storage
+            .backend
+            .iter()
+            .map(|a| a.id + 1)
+            .fold(next_account_id, AccountId::max);
+        storage.next_customer_id = storage
+            .backend
+            .customers()
+            .map(|c| c.id + 1)
+            .max()
+            .unwrap_or(1);
+        storage.next_hold_id = storage
+            .backend
+            .holds()
+            .map(|h| h.id + 1)
+            .fold(next_hold_id, HoldId::max);
+        storage.link_owners();
+        storage.open_cash();
+        storage
+    }
+
+    /// Оборотно-сальдовая ведомость по всем счетам банка
+    pub fn trial_balance(&self) -> Result<TrialBalance, BankError> {
+        TrialBalance::build(self.get_all().map(|a| (a.name.clone(), a.balance)))
+    }
+
+    /// Остатки счетов, сгруппированные по типам плана счетов
+    pub fn balances_by_type(&self) -> Result<Vec<AccountGroup>, BankError> {
+        AccountGroup::build(self.get_all().map(|a| (a.name.clone(), a.balance)))
+    }
+
+    /// Касса банка в валюте `change` после изменения на `change`
+    pub(crate) fn cash_after(&self, change: Money) -> Result<(Name, Balance), BankError> {
+        let cash = cash_account(change.currency());
+        let balance = self
+            .get_balance_internal(&cash)
+            .unwrap_or(Money::zero(change.currency()))
+            .checked_add(change)?;
+        Ok((cash, balance))
+    }
+
+    /// Данные, сохранённые до появления кассы, не знают о ней: считаем, что
+    /// все остатки клиентов когда-то были внесены наличными
+    fn open_cash(&mut self) {
+        if self.backend.iter().any(|a| is_internal(&a.name)) {
+            return;
+        }
+        let mut totals: Vec<Money> = Vec::new();
+        let balances: Vec<Money> = self.backend.iter().map(|a| a.balance).collect();
+        for balance in balances {
+            match totals
+                .iter_mut()
+                .find(|t| t.currency() == balance.currency())
+            {
+                Some(total) => match total.checked_add(balance) {
+                    Ok(sum) => *total = sum,
+                    // Переполнение покажет несходящаяся ведомость
+                    Err(_) => return,
+                },
+                None => totals.push(balance),
+            }
+        }
+        for total in totals.into_iter().filter(|t| !t.is_zero()) {
+            let cash = cash_account(total.currency());
+            let mut account =
+                Account::new(cash, Vec::new(), total.currency(), Timestamp::default());
+            account.balance = total;
+            self.load_account(account);
+        }
+    }
+
+    /// Данные, сохранённые до появления клиентов, не знают о них: каждый
+    /// счёт без владельцев получает клиента с тем же именем
+    fn link_owners(&mut self) {
+        let mut orphans: Vec<Account> = self
+            .backend
+            .iter()
+            .filter(|a| a.owners.is_empty() && !is_internal(&a.name))
+            .cloned()
+            .collect();
+        orphans.sort_by_key(|a| a.id);
+        for mut account in orphans {
+            let customer = Customer {
+                id: self.next_customer_id,
+                name: account.name.clone(),
+                since: account.opened_at,
+            };
+            account.owners = vec![customer.id];
+            self.load_customer(customer);
+            self.backend.put(account);
+        }
+    }
+
+    /// Подменяет источник времени (например, фиксированными часами в тестах)
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Журнал применённых транзакций
+    pub fn history(&self) -> &TxLog {
+        &self.history
+    }
+
+    /// Заменяет журнал транзакций восстановленным из хранилища
+    pub fn restore_history(&mut self, history: TxLog) {
+        self.history = history;
+    }
+
+    /// Применяет транзакцию и записывает её в журнал под новым номером
+    pub fn execute<T: Transaction + ?Sized>(
+        &mut self,
+        tx: &T,
+        memo: Option<&str>,
+    ) -> Result<Receipt, BankError> {
+        let receipt = self.history.next_receipt(self.clock.now(), memo);
+        self.execute_as(tx, receipt)
+    }
+
+    /// Применяет транзакцию не более одного раза для данного ключа.
+    /// Повтор с тем же ключом возвращает исходную квитанцию, не двигая деньги;
+    /// тот же ключ с другими параметрами — ошибка `IdempotencyConflict`.
+    /// Отклонённая транзакция ключ не занимает, её можно повторить.
+    pub fn execute_idempotent<T: Transaction + ?Sized>(
+        &mut self,
+        tx: &T,
+        key: &str,
+        memo: Option<&str>,
+    ) -> Result<Receipt, BankError> {
+        let mut receipt = self.history.next_receipt(self.clock.now(), memo);
+        receipt.idempotency_key = Some(key.to_string());
+        self.execute_as(tx, receipt)
+    }
+
+    /// Применяет транзакцию с уже выданной квитанцией (при воспроизведении журнала)
+    pub(crate) fn execute_as<T: Transaction + ?Sized>(
+        &mut self,
+        tx: &T,
+        receipt: Receipt,
+    ) -> Result<Receipt, BankError> {
+        if let Some(key) = &receipt.idempotency_key
+            && let Some((original, record)) = self.history.find_key(key)
+        {
+            return if *record == tx.journal_record() {
+                Ok(original.clone())
+            } else {
+                Err(BankError::IdempotencyConflict {
+                    key: key.clone(),
+                    original: original.id,
+                })
+            };
+        }
+
+        self.current_tx = Some(receipt.clone());
+        let posted = tx.post(self);
+        self.current_tx = None;
+        posted?;
+
+        self.history.push(tx.journal_record(), receipt.clone());
+        Ok(receipt)
+    }
+
+    /// Применяет компенсирующую транзакцию для транзакции `id` и связывает их.
+    /// Каждую транзакцию можно отменить только один раз.
+    pub fn reverse(&mut self, id: TxId, memo: Option<&str>) -> Result<Receipt, BankError> {
+        let receipt = self.history.next_receipt(self.clock.now(), memo);
+        self.reverse_as(id, receipt)
+    }
+
+    /// Отменяет транзакцию `id`, выдавая отмене готовую квитанцию
+    pub(crate) fn reverse_as(&mut self, id: TxId, receipt: Receipt) -> Result<Receipt, BankError> {
+        let entry = self
+            .history
+            .get(id)
+            .ok_or(BankError::TransactionNotFound(id))?;
+        if let Some(by) = entry.reversed_by {
+            return Err(BankError::AlreadyReversed { id, by });
+        }
+        let inverse = entry
+            .record
+            .to_transaction()
+            .expect("в журнале транзакций хранятся только транзакции")
+            .inverse();
+
+        self.current_tx = Some(receipt.clone());
+        self.compensating = true;
+        let posted = inverse.post(self);
+        self.compensating = false;
+        self.current_tx = None;
+        posted?;
+
+        self.history
+            .push_reversal(inverse.journal_record(), receipt.clone(), id);
+        Ok(receipt)
+    }
+
+    /// Делает все изменения долговечными средствами хранилища
+    pub fn commit(&mut self) -> Result<(), BankError> {
+        // Поля берутся по отдельности: хранилище изменяется, пока снимок читается
+        let snapshot = Snapshot {
+            journal_seq: self.journal_seq,
+            history: &self.history,
+            next_account_id: self.next_account_id,
+            next_hold_id: self.next_hold_id,
+            postings: &self.postings,
+            committed_at: self.clock.now(),
+        };
+        self.backend.commit(&snapshot)
+    }
+
+    /// Состояние банка вне хранилища на текущий момент
+    pub(crate) fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot {
+            journal_seq: self.journal_seq,
+            history: &self.history,
+            next_account_id: self.next_account_id,
+            next_hold_id: self.next_hold_id,
+            postings: &self.postings,
+            committed_at: self.clock.now(),
+        }
+    }
+
+    /// Все проводки по счетам в порядке их появления
+    pub fn postings(&self) -> &[Posting] {
+        &self.postings
+    }
+
+    /// Заменяет историю проводок восстановленной из хранилища
+    pub fn restore_postings(&mut self, postings: Vec<Posting>) {
+        self.recorded_postings = postings.len();
+        self.postings = postings;
+    }
+
+    /// Выписка по счёту за период `[from, to)`: входящий остаток, каждая
+    /// проводка с остатком после неё и исходящий остаток.
+    /// Работает и для удалённых счетов, пока о них сохранилась история.
+    pub fn statement(
+        &self,
+        name: &Name,
+        from: Option<Timestamp>,
+        to: Option<Timestamp>,
+    ) -> Result<Statement, BankError> {
+        let current = self
+            .current_or_closed(name)
+            .ok_or_else(|| BankError::AccountNotFound(name.clone()))?;
+        Statement::build(name, current, &self.postings, from, to)
+    }
+
+    /// Баланс счёта в момент `at` с учётом всех проводок не позже него.
+    /// None, если счёта тогда не было: он ещё не открыт или уже закрыт.
+    pub fn balance_at(&self, name: &Name, at: Timestamp) -> Option<Balance> {
+        let current = self.current_or_closed(name)?;
+        let postings = self.postings.iter().filter(|p| &p.account == name);
+
+        // Счёт без записи об открытии существовал до начала истории
+        let mut exists = postings
+            .clone()
+            .next()
+            .is_none_or(|p| p.kind != PostingKind::Open);
+        for posting in postings.clone().filter(|p| p.at <= at) {
+            match posting.kind {
+                PostingKind::Open => exists = true,
+                PostingKind::Close => exists = false,
+                PostingKind::Movement => {}
+            }
+        }
+        if !exists {
+            return None;
+        }
+
+        // Откатываем назад всё, что произошло позже
+        postings
+            .filter(|p| p.at > at && !p.amount.is_zero())
+            .try_fold(current, |balance, p| balance.checked_sub(p.amount))
+            .ok()
+    }
+
+    /// Банк в памяти с балансами и проводками на момент `at`
+    pub fn snapshot_at(&self, at: Timestamp) -> Storage {
+        let mut names: Vec<Name> = self.get_all().map(|a| a.name.clone()).collect();
+        names.extend(self.postings.iter().map(|p| p.account.clone()));
+        names.sort();
+        names.dedup();
+
+        let mut snapshot = Storage::new();
+        for customer in self.customers().filter(|c| c.since <= at) {
+            snapshot.load_customer(customer.clone());
+        }
+        for name in names {
+            if let Some(balance) = self.balance_at(&name, at) {
+                // Закрытый счёт восстанавливается без прежних реквизитов
+                let mut account = self.get_account(&name).cloned().unwrap_or_else(|| {
+                    Account::new(name, Vec::new(), balance.currency(), Timestamp::default())
+                });
+                account.balance = balance;
+                snapshot.load_account(account);
+            }
+        }
+        snapshot.restore_postings(
+            self.postings
+                .iter()
+                .filter(|p| p.at <= at)
+                .cloned()
+                .collect(),
+        );
+        snapshot
+    }
+
+    /// Текущий баланс; у удалённого счёта с историей — ноль в его валюте
+    fn current_or_closed(&self, name: &Name) -> Option<Balance> {
+        if let Some(balance) = self.get_balance_internal(name) {
+            return Some(balance);
         }
+        self.postings
+            .iter()
+            .rev()
+            .find(|p| &p.account == name)
+            .map(|last| Money::zero(last.amount.currency()))
+    }
+
+    /// Открывает точку сохранения: все последующие изменения можно откатить
+    pub(crate) fn savepoint(&mut self) -> Savepoint {
+        self.savepoints += 1;
+        Savepoint {
+            undo: self.undo.len(),
+            postings: self.postings.len(),
+            next_tx_id: self.history.next_id(),
+            next_account_id: self.next_account_id,
+            next_customer_id: self.next_customer_id,
+            next_hold_id: self.next_hold_id,
+        }
+    }
+
+    /// Отменяет все изменения, сделанные после точки сохранения
+    pub(crate) fn rollback_to(&mut self, savepoint: Savepoint) {
+        self.postings.truncate(savepoint.postings);
+        self.recorded_postings = self.recorded_postings.min(savepoint.postings);
+        self.history.truncate(savepoint.next_tx_id);
+        self.next_account_id = savepoint.next_account_id;
+        self.next_customer_id = savepoint.next_customer_id;
+        self.next_hold_id = savepoint.next_hold_id;
+        while self.undo.len() > savepoint.undo {
+            match self.undo.pop().unwrap() {
+                Undo::Account(_, Some(account)) => self.backend.put(account),
+                Undo::Account(name, None) => {
+                    self.backend.remove(&name);
+                }
+                Undo::Hold(_, Some(hold)) => self.backend.put_hold(hold),
+                Undo::Hold(id, None) => {
+                    self.backend.remove_hold(id);
+                }
+                Undo::Customer(_, Some(customer)) => self.backend.put_customer(customer),
+                Undo::Customer(id, None) => {
+                    self.backend.remove_customer(id);
+                }
+                Undo::DefaultLimits(currency, previous) => self
+                    .backend
+                    .put_default_limits(currency, previous.unwrap_or_default()),
+            }
+        }
+        self.release();
+    }
+
+    /// Закрывает точку сохранения, оставляя изменения в силе
+    pub(crate) fn release(&mut self) {
+        self.savepoints -= 1;
+        // Пока открыта внешняя точка сохранения, изменения ещё могут быть отменены
+        if self.savepoints == 0 {
+            self.undo.clear();
+            self.record_postings();
+        }
+    }
+
+    /// Вносит изменение `change` и сохраняет его через `persist`, например
+    /// записью в журнал. Если изменение или сохранение не удалось, банк
+    /// возвращается к прежнему состоянию, а несохранённые записи хранилища
+    /// отбрасываются, поэтому всё сделанное раньше должно быть уже сохранено.
+    pub fn apply_durably<T>(
+        &mut self,
+        change: impl FnOnce(&mut Storage) -> Result<T, BankError>,
+        persist: impl FnOnce(&mut Storage, &T) -> Result<(), BankError>,
+    ) -> Result<T, BankError> {
+        let savepoint = self.savepoint();
+        let value = match change(self) {
+            Ok(value) => value,
+            Err(e) => {
+                self.rollback_to(savepoint);
+                return Err(e);
+            }
+        };
+        // Проводки передаются хранилищу заранее, чтобы попасть в ту же фиксацию
+        self.record_postings();
+        match persist(self, &value) {
+            Ok(()) => {
+                self.release();
+                Ok(value)
+            }
+            Err(e) => {
+                self.backend.discard();
+                self.rollback_to(savepoint);
+                Err(e)
+            }
+        }
+    }
+
+    /// Время текущей транзакции, а вне транзакции — показания часов
+    pub(crate) fn now(&self) -> Timestamp {
+        match &self.current_tx {
+            Some(receipt) => receipt.applied_at,
+            None => self.replay_at.unwrap_or_else(|| self.clock.now()),
+        }
+    }
+
+    /// Вносит изменение так, будто оно сделано в момент `at`; без времени
+    /// (записи старых журналов) действуют показания часов
+    pub(crate) fn at_time<T>(
+        &mut self,
+        at: Option<Timestamp>,
+        change: impl FnOnce(&mut Storage) -> T,
+    ) -> T {
+        let previous = self.replay_at;
+        self.replay_at = at.or(previous);
+        let result = change(self);
+        self.replay_at = previous;
+        result
+    }
+
+    /// Добавляет проводку; вне точки сохранения она сразу уходит в хранилище
+    fn post_change(&mut self, kind: PostingKind, account: &Name, amount: Money) {
+        let (tx, memo) = match &self.current_tx {
+            Some(receipt) => (Some(receipt.id), receipt.memo.clone()),
+            None => (None, None),
+        };
+        self.postings.push(Posting {
+            at: self.now(),
+            kind,
+            account: account.clone(),
+            amount,
+            tx,
+            memo,
+        });
+        if self.savepoints == 0 {
+            self.record_postings();
+        }
+    }
+
+    fn record_postings(&mut self) {
+        for posting in &self.postings[self.recorded_postings..] {
+            self.backend.record_posting(posting);
+        }
+        self.recorded_postings = self.postings.len();
+    }
+
+    fn remember(&mut self, name: &Name) {
+        if self.savepoints > 0 {
+            let previous = self.backend.get(name).cloned();
+            self.undo.push(Undo::Account(name.clone(), previous));
+        }
+    }
+
+    fn remember_customer(&mut self, id: CustomerId) {
+        if self.savepoints > 0 {
+            let previous = self.backend.get_customer(id).cloned();
+            self.undo.push(Undo::Customer(id, previous));
+        }
+    }
+
+    fn remember_default_limits(&mut self, currency: Currency) {
+        if self.savepoints > 0 {
+            let previous = self
+                .backend
+                .default_limits()
+                .find(|(c, _)| *c == currency)
+                .map(|(_, limits)| limits);
+            self.undo.push(Undo::DefaultLimits(currency, previous));
+        }
+    }
+
+    fn remember_hold(&mut self, id: HoldId) {
+        if self.savepoints > 0 {
+            let previous = self.backend.get_hold(id).cloned();
+            self.undo.push(Undo::Hold(id, previous));
+        }
+    }
+
+    /// Передаёт хранилищу запись о применённой операции для истории
+    pub fn record(&mut self, record: &JournalRecord) -> Result<(), BankError> {
+        self.backend.record(record)
     }
 
     // Internal methods used by UserManager and BalanceManager
-    pub(crate) fn add_user_internal(&mut self, name: Name) -> Option<Balance> {
-        if let hash_map::Entry::Vacant(e) = self.accounts.entry(name) {
-            e.insert(0);
-            Some(0)
-        } else {
-            None
+    pub(crate) fn add_user_internal(&mut self, name: Name) -> Result<Balance, BankError> {
+        self.check_new_account(&name)?;
+        let customer = self.add_customer_internal(name.clone())?;
+        self.open_account_internal(name, vec![customer], Currency::default())?;
+        Ok(Money::zero(Currency::default()))
+    }
+
+    pub(crate) fn add_customer_internal(&mut self, name: String) -> Result<CustomerId, BankError> {
+        Customer::validate_name(&name)?;
+        let customer = Customer {
+            id: self.next_customer_id,
+            name,
+            since: self.now(),
+        };
+        let id = customer.id;
+        self.load_customer(customer);
+        Ok(id)
+    }
+
+    /// Кладёт клиента из файла или журнала под его собственным номером
+    pub(crate) fn load_customer(&mut self, customer: Customer) {
+        self.next_customer_id = self.next_customer_id.max(customer.id + 1);
+        self.remember_customer(customer.id);
+        self.backend.put_customer(customer);
+    }
+
+    pub fn get_customer(&self, id: CustomerId) -> Option<&Customer> {
+        self.backend.get_customer(id)
+    }
+
+    /// Все клиенты по порядку номеров
+    pub fn customers(&self) -> impl Iterator<Item = &Customer> + '_ {
+        self.backend.customers()
+    }
+
+    /// Счета, которыми владеет клиент, в том числе совместно с другими
+    pub fn accounts_of(&self, customer: CustomerId) -> Vec<&Account> {
+        let mut accounts: Vec<&Account> = self
+            .get_all()
+            .filter(|a| a.owners.contains(&customer))
+            .collect();
+        accounts.sort_by_key(|a| a.id);
+        accounts
+    }
+
+    /// Сумма остатков всех счетов клиента по валютам; совместные счета
+    /// учитываются полностью
+    pub fn customer_position(
+        &self,
+        customer: CustomerId,
+    ) -> Result<BTreeMap<Currency, Money>, BankError> {
+        if self.get_customer(customer).is_none() {
+            return Err(BankError::CustomerNotFound(customer));
+        }
+        let mut totals = BTreeMap::new();
+        for account in self.accounts_of(customer) {
+            let total = totals
+                .entry(account.currency())
+                .or_insert(Money::zero(account.currency()));
+            *total = total.checked_add(account.balance)?;
+        }
+        Ok(totals)
+    }
+
+    /// Имя счёта попадает в строки CSV и журнала, где ',' и ';' разделяют поля и операции
+    fn check_new_account(&self, name: &Name) -> Result<(), BankError> {
+        if name.trim().is_empty() || name.contains([',', ';', '\n', '\r']) {
+            return Err(BankError::Parse(format!(
+                "некорректное имя счёта '{}'",
+                name
+            )));
+        }
+        if is_internal(name) {
+            return Err(BankError::ReservedAccount(name.clone()));
+        }
+        if self.backend.get(name).is_some() {
+            return Err(BankError::DuplicateAccount(name.clone()));
         }
+        Ok(())
     }
 
-    pub(crate) fn remove_user_internal(&mut self, name: &Name) -> Option<Balance> {
-        self.accounts.remove(name)
+    pub(crate) fn open_account_internal(
+        &mut self,
+        name: Name,
+        mut owners: Vec<CustomerId>,
+        currency: Currency,
+    ) -> Result<AccountId, BankError> {
+        self.check_new_account(&name)?;
+        owners.sort();
+        owners.dedup();
+        if owners.is_empty() {
+            return Err(BankError::MissingOwner(name));
+        }
+        if let Some(&unknown) = owners.iter().find(|&&id| self.get_customer(id).is_none()) {
+            return Err(BankError::CustomerNotFound(unknown));
+        }
+        let account = Account::new(name.clone(), owners, currency, self.now());
+        self.remember(&name);
+        self.post_change(PostingKind::Open, &name, account.balance);
+        Ok(self.load_account(account))
     }
 
-    pub(crate) fn get_balance_internal(&self, name: &Name) -> Option<Balance> {
-        self.accounts.get(name).copied()
+    /// Удаляет счёт без следа в хранилище. Удалить можно только действующий
+    /// пустой счёт: остаток выплачивается закрытием счёта.
+    pub(crate) fn remove_user_internal(&mut self, name: &Name) -> Result<Balance, BankError> {
+        let account = self.open_customer_account(name)?;
+        if account.status != AccountStatus::Active {
+            return Err(BankError::AccountUnavailable {
+                name: name.clone(),
+                status: account.status,
+            });
+        }
+        let balance = account.balance;
+        if balance.is_negative() {
+            return Err(BankError::AccountOverdrawn(name.clone()));
+        }
+        self.check_no_holds(name, balance.currency())?;
+        if !balance.is_zero() {
+            return Err(BankError::AccountNotEmpty(name.clone()));
+        }
+
+        self.remember(name);
+        self.backend.remove(name);
+        self.post_change(PostingKind::Close, name, balance);
+        Ok(balance)
     }
 
-    pub(crate) fn deposit_internal(&mut self, name: &Name, amount: Balance) -> Result<(), String> {
-        if let Some(balance) = self.accounts.get_mut(name) {
-            *balance += amount;
+    /// Проверяет, что статус счёта позволяет списать с него деньги
+    /// (`AccountSide::Source`) или зачислить их (`AccountSide::Destination`).
+    /// Отмена транзакции списывает и с замороженного или неактивного счёта.
+    /// Счета банка и несуществующие счета не проверяются.
+    pub(crate) fn check_status(&self, name: &Name, side: AccountSide) -> Result<(), BankError> {
+        let Some(account) = self.backend.get(name) else {
+            return Ok(());
+        };
+        let allowed = match side {
+            AccountSide::Source if self.compensating => account.status != AccountStatus::Closed,
+            AccountSide::Source => account.status.allows_debits(),
+            AccountSide::Destination => account.status.allows_credits(),
+        };
+        if allowed {
             Ok(())
         } else {
-            Err("Пользователь не найден".into())
+            Err(BankError::AccountUnavailable {
+                name: name.clone(),
+                status: account.status,
+            })
         }
     }
 
-    pub(crate) fn withdraw_internal(&mut self, name: &Name, amount: Balance) -> Result<(), String> {
-        if let Some(balance) = self.accounts.get_mut(name) {
-            if *balance >= amount {
-                *balance -= amount;
-                Ok(())
+    /// Проверяет списание со счёта клиента, после которого на нём останется
+    /// `balance`: хватает ли денег (`check_funds`) и не превышает ли сумма
+    /// списания лимитов операции и дня.
+    /// Счета банка и отмены транзакций лимитами не ограничены.
+    pub(crate) fn check_debit(&self, name: &Name, balance: Balance) -> Result<(), BankError> {
+        self.check_funds(name, balance)?;
+        let Some(account) = self.backend.get(name).filter(|_| !is_internal(name)) else {
+            return Ok(());
+        };
+        if self.compensating {
+            return Ok(());
+        }
+        let limits = self.effective_limits(account);
+        let amount = account.balance.checked_sub(balance)?;
+        let exceeded = |kind, limit, remaining: Money| BankError::SpendingLimitExceeded {
+            name: name.clone(),
+            kind,
+            limit,
+            remaining: if remaining.is_negative() {
+                Money::zero(remaining.currency())
             } else {
-                Err("Недостаточно средств".into())
+                remaining
+            },
+        };
+        if let Some(limit) = limits.per_transaction
+            && limit.checked_sub(amount)?.is_negative()
+        {
+            return Err(exceeded(LimitKind::PerTransaction, limit, limit));
+        }
+        if let Some(limit) = limits.daily {
+            let remaining = limit.checked_sub(self.daily_outflow(name, limit.currency())?)?;
+            if remaining.checked_sub(amount)?.is_negative() {
+                return Err(exceeded(LimitKind::Daily, limit, remaining));
             }
+        }
+        Ok(())
+    }
+
+    /// Проверяет, что с `balance` на счёте клиента доступный остаток опускается
+    /// ниже нуля только в пределах овердрафта. Счета банка не проверяются.
+    pub(crate) fn check_funds(&self, name: &Name, balance: Balance) -> Result<(), BankError> {
+        let Some(account) = self.backend.get(name).filter(|_| !is_internal(name)) else {
+            return Ok(());
+        };
+        // Заблокированные суммы уже обещаны и списанию недоступны
+        let available = balance.checked_sub(self.held(name, balance.currency())?)?;
+        if available.is_negative() {
+            match self.effective_limits(account).overdraft {
+                None => return Err(BankError::InsufficientFunds(name.clone())),
+                Some(limit) if available.checked_add(limit)?.is_negative() => {
+                    return Err(BankError::OverdraftExceeded {
+                        name: name.clone(),
+                        limit,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Сумма действующих блокировок на счёте
+    fn held(&self, name: &Name, currency: Currency) -> Result<Money, BankError> {
+        let now = self.now();
+        let mut held = Money::zero(currency);
+        for hold in self
+            .backend
+            .holds()
+            .filter(|h| &h.account == name && h.is_active(now))
+        {
+            held = held.checked_add(hold.amount)?;
+        }
+        Ok(held)
+    }
+
+    /// Закрыть или удалить счёт с действующими блокировками нельзя: их уже
+    /// не списать, а обещанные деньги ушли бы вместе с остатком
+    fn check_no_holds(&self, name: &Name, currency: Currency) -> Result<(), BankError> {
+        if self.held(name, currency)?.is_zero() {
+            Ok(())
         } else {
-            Err("Пользователь не найден".into())
+            Err(BankError::AccountHasHolds(name.clone()))
         }
     }
 
-    pub fn get_all(&self) -> impl Iterator<Item = (Name, i64)> + '_ {
-        self.accounts.iter().map(|(n, b)| (n.clone(), *b))
+    /// Остаток за вычетом действующих блокировок. Каждая блокировка прошла
+    /// проверку списания, поэтому их сумма не переполняется.
+    pub fn available_balance(&self, name: &Name) -> Option<Balance> {
+        let balance = self.get_balance_internal(name)?;
+        self.held(name, balance.currency())
+            .and_then(|held| balance.checked_sub(held))
+            .ok()
     }
 
-    /// Загружает данные из CSV-файла или создаёт хранилище с дефолтными пользователями
+    /// Блокирует `amount` на счёте клиента до списания или снятия блокировки.
+    /// Блокировка проверяется так же, как списание той же суммы.
+    pub(crate) fn authorize_internal(
+        &mut self,
+        name: &Name,
+        amount: Money,
+        expires_at: Option<Timestamp>,
+    ) -> Result<HoldId, BankError> {
+        amount.ensure_positive()?;
+        let balance = self
+            .customer_balance(name, AccountSide::Source)?
+            .checked_sub(amount)?;
+        self.check_debit(name, balance)?;
+        let hold = Hold {
+            id: self.next_hold_id,
+            account: name.clone(),
+            amount,
+            placed_at: self.now(),
+            expires_at,
+        };
+        let id = hold.id;
+        self.load_hold(hold);
+        Ok(id)
+    }
+
+    /// Кладёт блокировку из файла или журнала под её собственным номером
+    pub(crate) fn load_hold(&mut self, hold: Hold) {
+        self.next_hold_id = self.next_hold_id.max(hold.id + 1);
+        self.remember_hold(hold.id);
+        self.backend.put_hold(hold);
+    }
+
+    /// Снимает блокировку, не списывая деньги
+    pub(crate) fn release_internal(&mut self, id: HoldId) -> Result<Hold, BankError> {
+        if self.backend.get_hold(id).is_none() {
+            return Err(BankError::HoldNotFound(id));
+        }
+        self.remember_hold(id);
+        Ok(self.backend.remove_hold(id).unwrap())
+    }
+
+    pub fn get_hold(&self, id: HoldId) -> Option<&Hold> {
+        self.backend.get_hold(id)
+    }
+
+    /// Все блокировки по порядку номеров, включая истёкшие
+    pub fn holds(&self) -> impl Iterator<Item = &Hold> + '_ {
+        self.backend.holds()
+    }
+
+    /// Удаляет истёкшие блокировки и возвращает их
+    pub(crate) fn expire_holds_internal(&mut self) -> Vec<Hold> {
+        let now = self.now();
+        let expired: Vec<HoldId> = self
+            .holds()
+            .filter(|h| !h.is_active(now))
+            .map(|h| h.id)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| self.release_internal(id).ok())
+            .collect()
+    }
+
+    /// Сколько ушло со счёта за последние сутки до текущего момента, вместе
+    /// с действующими блокировками, которые ещё могут быть списаны;
+    /// поступления списаний не компенсируют
+    fn daily_outflow(&self, name: &Name, currency: Currency) -> Result<Money, BankError> {
+        let now = self.now();
+        let since = Timestamp::from_unix(now.unix() - SECONDS_PER_DAY);
+        let mut outflow = Money::zero(currency);
+        for posting in self.postings.iter().filter(|p| {
+            &p.account == name
+                && p.kind == PostingKind::Movement
+                && p.amount.is_negative()
+                && since < p.at
+                && p.at <= now
+        }) {
+            outflow = outflow.checked_sub(posting.amount)?;
+        }
+        outflow.checked_add(self.held(name, currency)?)
+    }
+
+    /// Лимиты счёта; не заданные у счёта берутся из лимитов банка для его валюты
+    pub fn effective_limits(&self, account: &Account) -> AccountLimits {
+        let defaults = self
+            .default_limits()
+            .find(|(currency, _)| *currency == account.currency())
+            .map(|(_, limits)| limits)
+            .unwrap_or_default();
+        account.limits.or(defaults)
+    }
+
+    /// Лимиты банка по валютам для счетов, не задавших своих
+    pub fn default_limits(&self) -> impl Iterator<Item = (Currency, AccountLimits)> + '_ {
+        self.backend.default_limits()
+    }
+
+    /// Заменяет лимиты банка для счетов в `currency`
+    pub fn set_default_limits(
+        &mut self,
+        currency: Currency,
+        limits: AccountLimits,
+    ) -> Result<(), BankError> {
+        check_limits(&limits, currency)?;
+        self.remember_default_limits(currency);
+        self.backend.put_default_limits(currency, limits);
+        Ok(())
+    }
+
+    /// Заменяет лимиты счёта клиента; каждый лимит должен быть в валюте счёта
+    /// и не меньше нуля
+    pub(crate) fn set_limits_internal(
+        &mut self,
+        name: &Name,
+        limits: AccountLimits,
+    ) -> Result<(), BankError> {
+        let mut account = self.open_customer_account(name)?.clone();
+        check_limits(&limits, account.currency())?;
+        account.limits = limits;
+        self.remember(name);
+        self.backend.put(account);
+        Ok(())
+    }
+
+    /// Счёт клиента, статус которого можно изменить
+    fn open_customer_account(&self, name: &Name) -> Result<&Account, BankError> {
+        if is_internal(name) {
+            return Err(BankError::ReservedAccount(name.clone()));
+        }
+        let account = self
+            .backend
+            .get(name)
+            .ok_or_else(|| BankError::AccountNotFound(name.clone()))?;
+        if account.status == AccountStatus::Closed {
+            return Err(BankError::AccountUnavailable {
+                name: name.clone(),
+                status: account.status,
+            });
+        }
+        Ok(account)
+    }
+
+    /// Переводит счёт в `status`; закрытый счёт изменить нельзя,
+    /// а закрыть так можно только пустой счёт
+    pub(crate) fn set_status_internal(
+        &mut self,
+        name: &Name,
+        status: AccountStatus,
+    ) -> Result<(), BankError> {
+        if status == AccountStatus::Closed {
+            return self.close_account_internal(name, None).map(|_| ());
+        }
+        let mut account = self.open_customer_account(name)?.clone();
+        account.status = status;
+        self.remember(name);
+        self.backend.put(account);
+        Ok(())
+    }
+
+    /// Закрывает счёт, оставляя его в хранилище ради истории.
+    /// Остаток переводится на счёт `payout`; без него закрыть можно только
+    /// пустой счёт. Возвращает выплаченную сумму.
+    pub(crate) fn close_account_internal(
+        &mut self,
+        name: &Name,
+        payout: Option<&Name>,
+    ) -> Result<Balance, BankError> {
+        let mut account = self.open_customer_account(name)?.clone();
+        let balance = account.balance;
+        // Долг по овердрафту нельзя ни выплатить, ни списать закрытием счёта
+        if balance.is_negative() {
+            return Err(BankError::AccountOverdrawn(name.clone()));
+        }
+        self.check_no_holds(name, balance.currency())?;
+        match payout {
+            _ if balance.is_zero() => {}
+            None => return Err(BankError::AccountNotEmpty(name.clone())),
+            Some(payout) if payout == name => {
+                return Err(BankError::InvalidAccount {
+                    side: AccountSide::Destination,
+                    name: payout.clone(),
+                });
+            }
+            // Выплата проходит те же проверки и лимиты, что и любой перевод
+            Some(payout) => Transfer {
+                from: name.clone(),
+                to: payout.clone(),
+                amount: balance,
+            }
+            .post(self)?,
+        }
+        self.remember(name);
+        account.balance = Money::zero(balance.currency());
+        account.status = AccountStatus::Closed;
+        self.backend.put(account);
+        self.post_change(PostingKind::Close, name, Money::zero(balance.currency()));
+        Ok(balance)
+    }
+
+    /// Кладёт счёт из файла или базы, не считая это изменением баланса.
+    /// Счёт без номера получает следующий свободный.
+    pub(crate) fn load_account(&mut self, mut account: Account) -> AccountId {
+        if account.id == 0 {
+            account.id = self.next_account_id;
+        }
+        self.next_account_id = self.next_account_id.max(account.id + 1);
+        let id = account.id;
+        self.backend.put(account);
+        id
+    }
+
+    pub(crate) fn get_balance_internal(&self, name: &Name) -> Option<Balance> {
+        self.backend.get(name).map(|a| a.balance)
+    }
+
+    /// Счёт со всеми реквизитами
+    pub fn get_account(&self, name: &Name) -> Option<&Account> {
+        self.backend.get(name)
+    }
+
+    pub(crate) fn set_balance_internal(&mut self, name: &Name, balance: Balance) {
+        self.remember(name);
+        let change = match self.backend.get(name) {
+            Some(previous) if previous.currency() == balance.currency() => {
+                balance.minor_units() - previous.balance.minor_units()
+            }
+            _ => balance.minor_units(),
+        };
+        if change != 0 {
+            self.post_change(
+                PostingKind::Movement,
+                name,
+                Money::new(change, balance.currency()),
+            );
+        }
+        // Счета банка открываются первой проводкой по ним
+        let mut account = self.backend.get(name).cloned().unwrap_or_else(|| {
+            Account::new(name.clone(), Vec::new(), balance.currency(), self.now())
+        });
+        account.balance = balance;
+        self.load_account(account);
+    }
+
+    pub(crate) fn deposit_internal(
+        &mut self,
+        name: &Name,
+        amount: Balance,
+    ) -> Result<(), BankError> {
+        amount.ensure_positive()?;
+        let balance = self
+            .customer_balance(name, AccountSide::Destination)?
+            .checked_add(amount)?;
+        let (cash, cash_balance) = self.cash_after(amount)?;
+        self.set_balance_internal(name, balance);
+        self.set_balance_internal(&cash, cash_balance);
+        Ok(())
+    }
+
+    pub(crate) fn withdraw_internal(
+        &mut self,
+        name: &Name,
+        amount: Balance,
+    ) -> Result<(), BankError> {
+        amount.ensure_positive()?;
+        let balance = self
+            .customer_balance(name, AccountSide::Source)?
+            .checked_sub(amount)?;
+        self.check_debit(name, balance)?;
+        let (cash, cash_balance) = self.cash_after(amount.checked_neg()?)?;
+        self.set_balance_internal(name, balance);
+        self.set_balance_internal(&cash, cash_balance);
+        Ok(())
+    }
+
+    /// Баланс счёта клиента, участвующего в операции на стороне `side`.
+    /// Счета банка меняются только встречной проводкой, а статус счёта
+    /// должен допускать движение денег на этой стороне.
+    pub(crate) fn customer_balance(
+        &self,
+        name: &Name,
+        side: AccountSide,
+    ) -> Result<Balance, BankError> {
+        let balance = self
+            .get_balance_internal(name)
+            .filter(|_| !is_internal(name))
+            .ok_or_else(|| BankError::InvalidAccount {
+                side,
+                name: name.clone(),
+            })?;
+        self.check_status(name, side)?;
+        Ok(balance)
+    }
+
+    pub fn get_all(&self) -> impl Iterator<Item = &Account> + '_ {
+        self.backend.iter()
+    }
+
+    /// Загружает данные из CSV-файла или создаёт хранилище с дефолтными пользователями.
+    /// Некорректные строки пропускаются; паникует, если файл не удалось прочитать.
     pub fn load_data(file: &str) -> Storage {
-        let mut storage = Storage::new();
+        Self::try_load_data(file, LoadMode::Lenient)
+            .expect("Не удалось прочитать файл")
+            .storage
+    }
+
+    /// Загружает данные из CSV-файла, не паникуя.
+    /// В режиме `LoadMode::Strict` файл с любой некорректной строкой отвергается целиком,
+    /// а в `LoadMode::Lenient` такие строки пропускаются и попадают в отчёт.
+    /// Резервная копия загружается, только если основного файла нет: она хранит
+    /// более старое поколение, и подмена ею основного файла потеряла бы
+    /// уже сохранённые изменения.
+    pub fn try_load_data(file: &str, mode: LoadMode) -> Result<LoadReport, BankError> {
+        let backup = backup_path(file);
 
         // Проверяем, существует ли файл
-        if Path::new(file).exists() {
-            // Открываем файл
-            let file = File::open(file).unwrap();
-
-            // Оборачиваем файл в BufReader
-            // BufReader читает данные блоками и хранит их в буфере,
-            // поэтому построчное чтение (lines()) работает быстрее, чем читать по байту
-            let reader = io::BufReader::new(file);
-
-            // Читаем файл построчно
-            for line in reader.lines().map_while(Result::ok) {
-                // Разделяем строку по запятой: "Name,Balance"
-                let parts: Vec<&str> = line.trim().split(',').collect();
-
-                if parts.len() == 2 {
-                    let name = parts[0].to_string();
-                    // Пробуем преобразовать баланс из строки в число
-                    let balance: i64 = parts[1].parse().unwrap_or(0);
-
-                    // Добавляем пользователя и выставляем баланс
-                    UserManager::add_user(&mut storage, name.clone());
-                    let _ = BalanceManager::deposit(&mut storage, &name, balance);
+        if !Path::new(file).exists() {
+            // Сбой между копированием и переименованием при сохранении оставляет
+            // только резервную копию — восстанавливаемся из неё
+            if Path::new(&backup).exists() {
+                let mut report = Self::read_file(&backup, mode)?;
+                report.source = LoadSource::Backup;
+                return Ok(report);
+            }
+
+            // если файла нет, создаём пользователей с нуля; дата открытия у них
+            // постоянная, чтобы не меняться при каждом запуске без снимка
+            let mut storage = Storage::new();
+            storage.at_time(Some(Timestamp::default()), |storage| {
+                for u in ["John", "Alice", "Bob", "Vasya"] {
+                    UserManager::add_user(storage, u.to_string())?;
+                }
+                Ok::<_, BankError>(())
+            })?;
+            return Ok(LoadReport {
+                storage,
+                skipped: Vec::new(),
+                source: LoadSource::Defaults,
+            });
+        }
+
+        Self::read_file(file, mode)
+    }
+
+    fn read_file(file: &str, mode: LoadMode) -> Result<LoadReport, BankError> {
+        // Оборачиваем файл в BufReader
+        // BufReader читает данные блоками и хранит их в буфере,
+        // поэтому построчное чтение (lines()) работает быстрее, чем читать по байту
+        let reader = io::BufReader::new(File::open(file)?);
+        Self::read_csv(reader, mode)
+    }
+
+    /// Читает счета в формате `CSV_HEADER` или старом "Name,Balance" из любого источника.
+    /// Каждая некорректная строка попадает в отчёт с номером и причиной.
+    pub fn read_csv<R: BufRead>(reader: R, mode: LoadMode) -> Result<LoadReport, BankError> {
+        let mut storage = Storage::new();
+        let mut skipped = Vec::new();
+        let mut postings = Vec::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line_no = index + 1;
+
+            // Пустые строки не считаются ошибкой
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // Служебные строки "#key,value" хранят метаданные снимка
+            if let Some(meta) = line.trim().strip_prefix('#') {
+                match meta.split_once(',') {
+                    Some(("journal_seq", seq)) => match seq.parse() {
+                        Ok(seq) => storage.journal_seq = seq,
+                        Err(_) => skipped.push(LineError {
+                            line: line_no,
+                            reason: format!("некорректный номер журнала '{}'", seq),
+                        }),
+                    },
+                    Some(("tx", record)) => match record.parse() {
+                        Ok(record) => storage.history.restore(record),
+                        Err(_) => skipped.push(LineError {
+                            line: line_no,
+                            reason: format!("некорректная транзакция '{}'", record),
+                        }),
+                    },
+                    Some(("next_tx_id", id)) => match id.parse() {
+                        Ok(id) => storage.history.set_next_id(id),
+                        Err(_) => skipped.push(LineError {
+                            line: line_no,
+                            reason: format!("некорректный номер транзакции '{}'", id),
+                        }),
+                    },
+                    Some((name @ (NEXT_ACCOUNT_ID | NEXT_HOLD_ID), id)) => match id.parse() {
+                        Ok(id) if name == NEXT_ACCOUNT_ID => {
+                            storage.next_account_id = storage.next_account_id.max(id)
+                        }
+                        Ok(id) => storage.next_hold_id = storage.next_hold_id.max(id),
+                        Err(_) => skipped.push(LineError {
+                            line: line_no,
+                            reason: format!("некорректный номер '{}'", id),
+                        }),
+                    },
+                    Some(("idempotency", posted)) => match posted.parse() {
+                        Ok(JournalRecord::Posted { receipt, record }) => {
+                            storage.history.remember_key(receipt, *record)
+                        }
+                        _ => skipped.push(LineError {
+                            line: line_no,
+                            reason: format!("некорректный ключ идемпотентности '{}'", posted),
+                        }),
+                    },
+                    Some(("customer", customer)) => match customer.parse() {
+                        Ok(customer) => storage.load_customer(customer),
+                        Err(e) => skipped.push(LineError {
+                            line: line_no,
+                            reason: e.to_string(),
+                        }),
+                    },
+                    Some(("default_limits", defaults)) => {
+                        let parsed = defaults.split_once(',').ok_or_else(|| {
+                            BankError::Parse(format!("некорректные лимиты банка '{}'", defaults))
+                        });
+                        let result = parsed.and_then(|(currency, limits)| {
+                            storage.set_default_limits(currency.parse()?, limits.parse()?)
+                        });
+                        if let Err(e) = result {
+                            skipped.push(LineError {
+                                line: line_no,
+                                reason: e.to_string(),
+                            });
+                        }
+                    }
+                    Some(("hold", hold)) => match hold.parse() {
+                        Ok(hold) => storage.load_hold(hold),
+                        Err(e) => skipped.push(LineError {
+                            line: line_no,
+                            reason: e.to_string(),
+                        }),
+                    },
+                    Some(("posting", posting)) => match posting.parse::<Posting>() {
+                        Ok(posting) => postings.push(posting),
+                        Err(e) => skipped.push(LineError {
+                            line: line_no,
+                            reason: e.to_string(),
+                        }),
+                    },
+                    _ => {}
                 }
+                continue;
             }
-        } else {
-            // если файла нет, создаём пользователей с нуля
-            for u in ["John", "Alice", "Bob", "Vasya"] {
-                UserManager::add_user(&mut storage, u.to_string());
+
+            if line.trim() == CSV_HEADER {
+                continue;
+            }
+
+            match line.parse::<Account>() {
+                Ok(account) => {
+                    if storage.get_account(&account.name).is_some() {
+                        skipped.push(LineError {
+                            line: line_no,
+                            reason: format!("пользователь {} уже встречался", account.name),
+                        });
+                        continue;
+                    }
+                    storage.load_account(account);
+                }
+                Err(reason) => skipped.push(LineError {
+                    line: line_no,
+                    reason,
+                }),
             }
         }
 
-        storage
+        if mode == LoadMode::Strict && !skipped.is_empty() {
+            return Err(BankError::CorruptedData(skipped));
+        }
+        storage.link_owners();
+        storage.open_cash();
+        storage.restore_postings(postings);
+
+        Ok(LoadReport {
+            storage,
+            skipped,
+            source: LoadSource::Primary,
+        })
     }
 
-    /// Сохраняет текущее состояние Storage в CSV-файл
+    /// Сохраняет текущее состояние Storage в CSV-файл; паникует при ошибке записи
     pub fn save(&self, file: &str) {
-        let mut data = String::new();
+        self.try_save(file).expect("Не удалось записать файл");
+    }
 
-        // Собираем все данные в одну строку формата "Name,Balance"
-        for (name, balance) in self.get_all() {
-            data.push_str(&format!("{},{}\n", name, balance));
+    /// Загружает CSV-файл так же, как `try_load_data`, но возвращает банк,
+    /// который перезаписывает этот файл при каждом `commit`
+    pub fn open_csv(file: &str, mode: LoadMode) -> Result<LoadReport, BankError> {
+        let mut report = Self::try_load_data(file, mode)?;
+        let accounts: Vec<Account> = report.storage.get_all().cloned().collect();
+        let customers: Vec<Customer> = report.storage.customers().cloned().collect();
+        let journal_seq = report.storage.journal_seq;
+        let (next_account_id, next_hold_id) =
+            (report.storage.next_account_id, report.storage.next_hold_id);
+        let history = std::mem::take(&mut report.storage.history);
+        let postings = std::mem::take(&mut report.storage.postings);
+        let default_limits: Vec<_> = report.storage.default_limits().collect();
+        let holds: Vec<Hold> = report.storage.holds().cloned().collect();
+        report.storage = Storage::with_backend(CsvBackend::new(file, accounts, customers));
+        for (currency, limits) in default_limits {
+            report.storage.backend.put_default_limits(currency, limits);
         }
+        for hold in holds {
+            report.storage.load_hold(hold);
+        }
+        report.storage.journal_seq = journal_seq;
+        report.storage.next_account_id = report.storage.next_account_id.max(next_account_id);
+        report.storage.next_hold_id = report.storage.next_hold_id.max(next_hold_id);
+        report.storage.history = history;
+        report.storage.restore_postings(postings);
+        Ok(report)
+    }
+
+    /// Сохраняет текущее состояние Storage в CSV-файл, возвращая ошибку ввода-вывода.
+    /// Данные пишутся во временный файл, сбрасываются на диск и атомарно
+    /// переименовываются; предыдущее поколение остаётся в `<file>.bak`.
+    pub fn try_save(&self, file: &str) -> Result<(), BankError> {
+        let mut data = Vec::new();
+        self.write_csv(&mut data)?;
+        write_atomically(Path::new(file), &data)?;
+        Ok(())
+    }
+
+    /// Записывает все счета в формате `CSV_HEADER`, отсортированные по имени
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), BankError> {
+        self.snapshot().write_csv(writer, self.backend.as_ref())
+    }
+}
+
+/// Состояние банка, которое `Storage` держит вне хранилища: номер записи
+/// журнала, история транзакций, счётчики номеров и проводки.
+/// `StorageBackend::commit` сохраняет его вместе с собственными данными.
+pub struct Snapshot<'a> {
+    pub journal_seq: u64,
+    pub history: &'a TxLog,
+    pub next_account_id: AccountId,
+    pub next_hold_id: HoldId,
+    pub postings: &'a [Posting],
+    /// Время фиксации по часам банка
+    pub committed_at: Timestamp,
+}
 
-        // Записываем в файл
-        // Здесь мы не используем BufWriter, потому что сразу пишем всю строку целиком.
-        fs::write(file, data).expect("Не удалось записать файл");
+impl Snapshot<'_> {
+    /// Записывает снимок вместе со счетами, клиентами, лимитами и блокировками
+    /// из `backend` в формате CSV; счета в формате `CSV_HEADER`, по имени
+    pub fn write_csv<W: Write>(
+        &self,
+        mut writer: W,
+        backend: &dyn StorageBackend,
+    ) -> Result<(), BankError> {
+        if self.journal_seq > 0 {
+            writeln!(writer, "#journal_seq,{}", self.journal_seq)?;
+        }
+        // Номера транзакций продолжаются после перезапуска и не повторяются
+        if self.history.next_id() > 1 {
+            writeln!(writer, "#next_tx_id,{}", self.history.next_id())?;
+        }
+        // Как и номера транзакций, номера счетов и блокировок не повторяются
+        // после удаления счёта с наибольшим номером
+        for (name, next_id) in [
+            (NEXT_ACCOUNT_ID, self.next_account_id),
+            (NEXT_HOLD_ID, self.next_hold_id),
+        ] {
+            if next_id > 1 {
+                writeln!(writer, "#{},{}", name, next_id)?;
+            }
+        }
+        // Журнал после сжатия может отменить транзакцию из снимка,
+        // поэтому снимок хранит их все вместе со связями отмен
+        for entry in self.history.iter() {
+            writeln!(writer, "#tx,{}", entry.posted())?;
+        }
+        // Ключи идемпотентности должны пережить сжатие журнала
+        for (receipt, record) in self.history.keys() {
+            let posted = JournalRecord::Posted {
+                receipt: receipt.clone(),
+                record: Box::new(record.clone()),
+            };
+            writeln!(writer, "#idempotency,{}", posted)?;
+        }
+        for customer in backend.customers() {
+            writeln!(writer, "#customer,{}", customer)?;
+        }
+        for (currency, limits) in backend.default_limits() {
+            writeln!(writer, "#default_limits,{},{}", currency, limits)?;
+        }
+        for hold in backend.holds() {
+            writeln!(writer, "#hold,{}", hold)?;
+        }
+        // История проводок хранится в снимке вместе с балансами, которые она объясняет
+        for posting in self.postings {
+            writeln!(writer, "#posting,{}", posting)?;
+        }
+        let mut rows: Vec<_> = backend.iter().collect();
+        rows.sort_by(|a, b| a.name.cmp(&b.name));
+        writeln!(writer, "{}", CSV_HEADER)?;
+        for account in rows {
+            writeln!(writer, "{}", account)?;
+        }
+        Ok(())
     }
 }
 
+/// Лимиты должны быть в валюте счёта и не меньше нуля
+fn check_limits(limits: &AccountLimits, currency: Currency) -> Result<(), BankError> {
+    for limit in [limits.overdraft, limits.per_transaction, limits.daily]
+        .into_iter()
+        .flatten()
+    {
+        if limit.currency() != currency {
+            return Err(BankError::CurrencyMismatch {
+                expected: currency,
+                found: limit.currency(),
+            });
+        }
+        if limit.is_negative() {
+            return Err(BankError::InvalidAmount(format!(
+                "лимит не может быть отрицательным: {}",
+                limit
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Режим загрузки CSV-файла
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Некорректные строки пропускаются и попадают в отчёт
+    Lenient,
+    /// Любая некорректная строка — ошибка загрузки
+    Strict,
+}
+
+/// Откуда были загружены данные
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadSource {
+    /// Основной файл
+    Primary,
+    /// Резервная копия предыдущего поколения: основного файла не было
+    Backup,
+    /// Файлов нет, созданы пользователи по умолчанию
+    Defaults,
+}
+
+/// Результат загрузки: хранилище и пропущенные строки
+pub struct LoadReport {
+    pub storage: Storage,
+    /// Некорректные строки основного файла
+    pub skipped: Vec<LineError>,
+    pub source: LoadSource,
+}
+
+/// Атомарно заменяет содержимое файла, сохраняя предыдущее поколение в `<file>.bak`
+pub(crate) fn write_atomically(file: &Path, data: &[u8]) -> io::Result<()> {
+    let file = file
+        .to_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "путь к файлу не в UTF-8"))?;
+    let tmp = format!("{}.tmp", file);
+    {
+        let mut out = File::create(&tmp)?;
+        out.write_all(data)?;
+        out.sync_all()?;
+    }
+
+    // Сохраняем предыдущее поколение: основной файл при этом остаётся на месте,
+    // так что сбой в любой момент не оставит нас без целой копии
+    if Path::new(file).exists() {
+        let backup = backup_path(file);
+        fs::copy(file, &backup)?;
+        File::open(&backup)?.sync_all()?;
+    }
+
+    fs::rename(&tmp, file)?;
+    sync_parent_dir(file)
+}
+
+/// Путь к резервной копии предыдущего поколения файла
+pub fn backup_path(file: &str) -> String {
+    format!("{}.bak", file)
+}
+
+/// Сбрасывает на диск каталог, чтобы переименование файла пережило сбой
+fn sync_parent_dir(file: &str) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = match Path::new(file).parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = file;
+    Ok(())
+}
+
 impl Default for Storage {
     fn default() -> Self {
         Self::new()
@@ -127,7 +1545,9 @@ impl Default for Storage {
 }
 
 #[cfg(test)]
-use std::io::{BufReader, BufWriter, Cursor, Write};
+use crate::{balance_manager::BalanceManager, money::rub, transaction::Deposit};
+#[cfg(test)]
+use std::io::{BufReader, BufWriter, Cursor};
 
 #[test]
 fn test_load_data_existing_cursor() {
@@ -143,23 +1563,23 @@ fn test_load_data_existing_cursor() {
         let parts: Vec<&str> = line.trim().split(',').collect();
         if parts.len() == 2 {
             let name = parts[0].to_string();
-            let balance: i64 = parts[1].parse().unwrap_or(0);
-            UserManager::add_user(&mut storage, name.clone());
+            let balance = rub(parts[1].parse().unwrap_or(0));
+            UserManager::add_user(&mut storage, name.clone()).unwrap();
             BalanceManager::deposit(&mut storage, &name, balance).unwrap();
         }
     }
 
     assert_eq!(
         BalanceManager::get_balance(&storage, &"John".to_string()),
-        Some(100)
+        Some(rub(100))
     );
     assert_eq!(
         BalanceManager::get_balance(&storage, &"Alice".to_string()),
-        Some(200)
+        Some(rub(200))
     );
     assert_eq!(
         BalanceManager::get_balance(&storage, &"Bob".to_string()),
-        Some(50)
+        Some(rub(50))
     );
     assert_eq!(
         BalanceManager::get_balance(&storage, &"Vasya".to_string()),
@@ -171,18 +1591,18 @@ fn test_load_data_existing_cursor() {
 fn test_save_writes_to_cursor_correctly() {
     // Создаём Storage и добавляем пользователей
     let mut storage = Storage::new();
-    UserManager::add_user(&mut storage, "John".to_string());
-    UserManager::add_user(&mut storage, "Alice".to_string());
-    BalanceManager::deposit(&mut storage, &"John".to_string(), 150).unwrap();
-    BalanceManager::deposit(&mut storage, &"Alice".to_string(), 300).unwrap();
+    UserManager::add_user(&mut storage, "John".to_string()).unwrap();
+    UserManager::add_user(&mut storage, "Alice".to_string()).unwrap();
+    BalanceManager::deposit(&mut storage, &"John".to_string(), rub(150)).unwrap();
+    BalanceManager::deposit(&mut storage, &"Alice".to_string(), rub(300)).unwrap();
 
     // Сохраняем в память через BufWriter
     let buffer = Vec::new();
     let mut cursor = Cursor::new(buffer);
     {
         let mut writer = BufWriter::new(&mut cursor);
-        for (name, balance) in storage.get_all() {
-            writeln!(writer, "{},{}", name, balance).unwrap();
+        for account in storage.get_all() {
+            writeln!(writer, "{},{}", account.name, account.balance).unwrap();
         }
         writer.flush().unwrap();
     }
@@ -192,5 +1612,314 @@ fn test_save_writes_to_cursor_correctly() {
     let mut lines: Vec<String> = BufReader::new(cursor).lines().map(|l| l.unwrap()).collect();
     lines.sort(); // сортируем для сравнения
 
-    assert_eq!(lines, vec!["Alice,300", "John,150"]);
+    assert_eq!(
+        lines,
+        vec![
+            "@cash:RUB,450.00 RUB",
+            "Alice,300.00 RUB",
+            "John,150.00 RUB"
+        ]
+    );
+}
+
+#[test]
+fn test_read_csv_reports_bad_lines() {
+    let data = b"John,100\nAlice,abc\n\nBob\nJohn,5\nVasya,7\n";
+
+    let report = Storage::read_csv(Cursor::new(&data[..]), LoadMode::Lenient).unwrap();
+
+    assert_eq!(
+        report.skipped.iter().map(|e| e.line).collect::<Vec<_>>(),
+        vec![2, 4, 5]
+    );
+    assert_eq!(
+        BalanceManager::get_balance(&report.storage, &"John".to_string()),
+        Some(rub(100))
+    );
+    // Некорректный баланс больше не превращается в 0
+    assert_eq!(
+        BalanceManager::get_balance(&report.storage, &"Alice".to_string()),
+        None
+    );
+    assert_eq!(
+        BalanceManager::get_balance(&report.storage, &"Vasya".to_string()),
+        Some(rub(7))
+    );
+}
+
+#[test]
+fn test_read_csv_strict_rejects_corrupted_file() {
+    let data = b"John,100\nAlice,abc\n";
+
+    match Storage::read_csv(Cursor::new(&data[..]), LoadMode::Strict) {
+        Err(BankError::CorruptedData(errors)) => {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].line, 2);
+        }
+        _ => panic!("ожидалась ошибка CorruptedData"),
+    }
+}
+
+#[test]
+fn test_write_csv_round_trip() {
+    let data = b"John,100\nAlice,200\n";
+    let storage = Storage::read_csv(Cursor::new(&data[..]), LoadMode::Strict)
+        .unwrap()
+        .storage;
+
+    let mut buffer = Vec::new();
+    storage.write_csv(&mut buffer).unwrap();
+
+    // Старый файл из двух колонок сохраняется в новом формате с заголовком,
+    // а каждый счёт получает одноимённого клиента
+    assert_eq!(
+        String::from_utf8(buffer.clone()).unwrap(),
+        "#next_account_id,4\n\
+         #customer,1,0,John\n\
+         #customer,2,0,Alice\n\
+         name,balance,id,owners,opened_at,status,limits\n\
+         @cash:RUB,300.00 RUB,3,,0,active,\n\
+         Alice,200.00 RUB,2,2,0,active,\n\
+         John,100.00 RUB,1,1,0,active,\n"
+    );
+
+    let restored = Storage::read_csv(Cursor::new(buffer), LoadMode::Strict)
+        .unwrap()
+        .storage;
+    let mut accounts: Vec<_> = restored.get_all().cloned().collect();
+    accounts.sort_by_key(|a| a.id);
+    let mut expected: Vec<_> = storage.get_all().cloned().collect();
+    expected.sort_by_key(|a| a.id);
+    assert_eq!(accounts, expected);
+}
+
+#[test]
+fn test_default_limits_round_trip() {
+    let mut storage = Storage::new();
+    let limits = AccountLimits {
+        daily: Some(rub(1000)),
+        ..AccountLimits::default()
+    };
+    storage.set_default_limits(Currency::RUB, limits).unwrap();
+    assert!(storage.set_default_limits(Currency::EUR, limits).is_err());
+
+    let mut buffer = Vec::new();
+    storage.write_csv(&mut buffer).unwrap();
+    let text = String::from_utf8(buffer.clone()).unwrap();
+    assert!(text.starts_with("#default_limits,RUB,daily=1000.00 RUB\n"));
+
+    let restored = Storage::read_csv(Cursor::new(buffer), LoadMode::Strict)
+        .unwrap()
+        .storage;
+    assert_eq!(
+        restored.default_limits().collect::<Vec<_>>(),
+        [(Currency::RUB, limits)]
+    );
+}
+
+#[test]
+fn test_snapshot_keeps_idempotency_keys_and_postings() {
+    let data = b"John,100\nAlice,0\n";
+    let mut storage = Storage::read_csv(Cursor::new(&data[..]), LoadMode::Strict)
+        .unwrap()
+        .storage;
+    let transfer = Transfer {
+        from: "John".to_string(),
+        to: "Alice".to_string(),
+        amount: rub(10),
+    };
+    let receipt = transfer.apply_idempotent(&mut storage, "req-1").unwrap();
+
+    let mut buffer = Vec::new();
+    storage.write_csv(&mut buffer).unwrap();
+    let mut restored = Storage::read_csv(Cursor::new(buffer), LoadMode::Strict)
+        .unwrap()
+        .storage;
+
+    // Повтор после перезапуска возвращает исходную квитанцию
+    assert_eq!(
+        transfer.apply_idempotent(&mut restored, "req-1").unwrap(),
+        receipt
+    );
+    assert_eq!(
+        BalanceManager::get_balance(&restored, &"Alice".to_string()),
+        Some(rub(10))
+    );
+    assert_eq!(restored.history().next_id(), receipt.id + 1);
+    assert_eq!(restored.postings(), storage.postings());
+}
+
+#[cfg(test)]
+fn temp_csv(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("bank-{}-{}.csv", name, std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(backup_path(&path));
+    path
+}
+
+#[test]
+fn test_save_keeps_previous_generation() {
+    let file = temp_csv("save-backup");
+    let mut storage = Storage::new();
+    UserManager::add_user(&mut storage, "John".to_string()).unwrap();
+    storage.try_save(&file).unwrap();
+    assert!(!Path::new(&backup_path(&file)).exists());
+
+    BalanceManager::deposit(&mut storage, &"John".to_string(), rub(10)).unwrap();
+    storage.try_save(&file).unwrap();
+
+    // Метаданные (история проводок) и реквизиты счетов здесь не важны
+    let rows = |path: &str| -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .skip_while(|l| l.starts_with('#'))
+            .skip(1)
+            .map(|l| l.split(',').take(2).collect::<Vec<_>>().join(","))
+            .collect()
+    };
+    assert_eq!(rows(&file), ["@cash:RUB,10.00 RUB", "John,10.00 RUB"]);
+    assert_eq!(rows(&backup_path(&file)), ["John,0.00 RUB"]);
+    assert!(!Path::new(&format!("{}.tmp", file)).exists());
+
+    let _ = fs::remove_file(&file);
+    let _ = fs::remove_file(backup_path(&file));
+}
+
+#[test]
+fn test_load_prefers_primary_over_backup() {
+    let file = temp_csv("recover");
+    fs::write(&file, "John,10\nAli").unwrap();
+    fs::write(backup_path(&file), "John,5\nAlice,7\n").unwrap();
+
+    // Строгий режим не подменяет повреждённый файл старым поколением
+    assert!(matches!(
+        Storage::try_load_data(&file, LoadMode::Strict),
+        Err(BankError::CorruptedData(errors)) if errors.len() == 1
+    ));
+
+    // Мягкий режим берёт основной файл без некорректных строк
+    let report = Storage::try_load_data(&file, LoadMode::Lenient).unwrap();
+    assert_eq!(report.source, LoadSource::Primary);
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(
+        BalanceManager::get_balance(&report.storage, &"John".to_string()),
+        Some(rub(10))
+    );
+
+    // Без основного файла остаётся только резервная копия
+    fs::remove_file(&file).unwrap();
+    let report = Storage::try_load_data(&file, LoadMode::Strict).unwrap();
+    assert_eq!(report.source, LoadSource::Backup);
+    assert_eq!(
+        BalanceManager::get_balance(&report.storage, &"Alice".to_string()),
+        Some(rub(7))
+    );
+
+    let _ = fs::remove_file(backup_path(&file));
+}
+
+#[test]
+fn test_apply_durably_rolls_back_unpersisted_change() {
+    let mut storage = Storage::new();
+    UserManager::add_user(&mut storage, "John".to_string()).unwrap();
+    let deposit = Deposit {
+        account: "John".to_string(),
+        amount: rub(100),
+    };
+    let deposit = storage.execute(&deposit, None).unwrap().id;
+    let mut before: Vec<Account> = storage.get_all().cloned().collect();
+    before.sort_by_key(|a| a.id);
+    let failed = || Err(BankError::Io(io::Error::other("диск заполнен")));
+
+    // Новый клиент со счётом и деньги, переведённые ему, исчезают целиком
+    let result = storage.apply_durably(
+        |storage| {
+            UserManager::add_user(storage, "Alice".to_string())?;
+            BalanceManager::withdraw(storage, &"John".to_string(), rub(30))?;
+            BalanceManager::deposit(storage, &"Alice".to_string(), rub(30))
+        },
+        |_, _| failed(),
+    );
+    assert!(matches!(result, Err(BankError::Io(_))));
+    let mut after: Vec<Account> = storage.get_all().cloned().collect();
+    after.sort_by_key(|a| a.id);
+    assert_eq!(after, before);
+    assert_eq!(storage.customers().count(), 1);
+
+    // Отмена не засчитывается, и исходную транзакцию можно отменить позже
+    let result = storage.apply_durably(|storage| storage.reverse(deposit, None), |_, _| failed());
+    assert!(result.is_err());
+    assert_eq!(storage.history().get(deposit).unwrap().reversed_by, None);
+    assert_eq!(
+        BalanceManager::get_balance(&storage, &"John".to_string()),
+        Some(rub(100))
+    );
+
+    // Номера транзакций и клиентов, выданные несохранённым изменениям, свободны
+    let reversal = storage
+        .apply_durably(|storage| storage.reverse(deposit, None), |_, _| Ok(()))
+        .unwrap();
+    assert_eq!(reversal.id, deposit + 1);
+    assert_eq!(
+        UserManager::add_customer(&mut storage, "Alice".to_string()).unwrap(),
+        2
+    );
+
+    // Сохранённое изменение остаётся в силе
+    BalanceManager::deposit(&mut storage, &"John".to_string(), rub(100)).unwrap();
+    storage
+        .apply_durably(
+            |storage| BalanceManager::withdraw(storage, &"John".to_string(), rub(40)),
+            |_, _| Ok(()),
+        )
+        .unwrap();
+    assert_eq!(
+        BalanceManager::get_balance(&storage, &"John".to_string()),
+        Some(rub(60))
+    );
+}
+
+#[test]
+fn test_snapshot_keeps_account_and_hold_ids() {
+    let data = b"John,100\nAlice,0\n";
+    let mut storage = Storage::read_csv(Cursor::new(&data[..]), LoadMode::Strict)
+        .unwrap()
+        .storage;
+    let id =
+        UserManager::open_account(&mut storage, "john-2".to_string(), &[1], Currency::RUB).unwrap();
+    UserManager::remove_user(&mut storage, &"john-2".to_string()).unwrap();
+    let hold = BalanceManager::authorize(&mut storage, &"John".to_string(), rub(5), None).unwrap();
+    BalanceManager::release(&mut storage, hold).unwrap();
+
+    let mut buffer = Vec::new();
+    storage.write_csv(&mut buffer).unwrap();
+    let mut restored = Storage::read_csv(Cursor::new(buffer), LoadMode::Strict)
+        .unwrap()
+        .storage;
+
+    // Номера удалённого счёта и снятой блокировки не достаются новым
+    let next = UserManager::open_account(&mut restored, "john-3".to_string(), &[1], Currency::RUB)
+        .unwrap();
+    assert_eq!(next, id + 1);
+    assert_eq!(
+        BalanceManager::authorize(&mut restored, &"John".to_string(), rub(5), None).unwrap(),
+        hold + 1
+    );
+}
+
+#[test]
+fn test_default_users_keep_opening_date() {
+    let file = std::env::temp_dir().join(format!("bank-defaults-{}.csv", std::process::id()));
+    let file = file.to_str().unwrap();
+    let _ = fs::remove_file(file);
+    let _ = fs::remove_file(backup_path(file));
+
+    let report = Storage::try_load_data(file, LoadMode::Strict).unwrap();
+    assert_eq!(report.source, LoadSource::Defaults);
+    let john = report.storage.get_account(&"John".to_string()).unwrap();
+    assert_eq!(john.opened_at, Timestamp::default());
+    let owner = report.storage.get_customer(john.owners[0]).unwrap();
+    assert_eq!(owner.since, Timestamp::default());
 }
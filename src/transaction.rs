@@ -1,43 +1,951 @@
-use crate::storage::Storage;
-
-#[derive(Debug)]
-pub enum TxError {
-    InsufficientFunds,
-    InvalidAccount,
-}
+use crate::{
+    error::{AccountSide, BankError},
+    exchange::Rate,
+    history::Receipt,
+    hold::HoldId,
+    journal::JournalRecord,
+    ledger::{
+        Side, account_type, exchange_account, fee_account, interest_account, is_internal,
+        normal_side,
+    },
+    money::{Currency, Money},
+    storage::{Name, Storage},
+};
 
 pub trait Transaction {
-    fn apply(&self, accounts: &mut Storage) -> Result<(), TxError>;
+    /// Changes the balances without recording the transaction anywhere.
+    /// Use `apply` instead; this is the building block for `Batch` and reversals.
+    fn post(&self, accounts: &mut Storage) -> Result<(), BankError>;
+
+    /// Applies the transaction and records it in the storage history
+    fn apply(&self, storage: &mut Storage) -> Result<Receipt, BankError> {
+        storage.execute(self, None)
+    }
+
+    /// Like `apply`, attaching a free-text memo or external reference
+    fn apply_with_memo(&self, storage: &mut Storage, memo: &str) -> Result<Receipt, BankError> {
+        storage.execute(self, Some(memo))
+    }
+
+    /// Like `apply`, but a retry with the same key returns the original
+    /// receipt instead of applying the transaction again
+    fn apply_idempotent(&self, storage: &mut Storage, key: &str) -> Result<Receipt, BankError> {
+        storage.execute_idempotent(self, key, None)
+    }
+
+    /// Describes the transaction for the journal and the transaction history
+    fn journal_record(&self) -> JournalRecord;
+
+    /// Builds the compensating transaction that undoes this one
+    fn inverse(&self) -> Box<dyn Transaction>;
 }
 
+/// Credits the customer; the money arrives in the bank's cash account
 pub struct Deposit {
     pub account: String,
-    pub amount: i64,
+    pub amount: Money,
 }
 
 impl Transaction for Deposit {
-    fn apply(&self, storage: &mut Storage) -> Result<(), TxError> {
-        *storage.accounts.entry(self.account.clone()).or_insert(0) += self.amount;
+    fn post(&self, storage: &mut Storage) -> Result<(), BankError> {
+        self.amount.ensure_positive()?;
+        let balance = storage
+            .customer_balance(&self.account, AccountSide::Destination)?
+            .checked_add(self.amount)?;
+        let (cash, cash_balance) = storage.cash_after(self.amount)?;
+
+        storage.set_balance_internal(&self.account, balance);
+        storage.set_balance_internal(&cash, cash_balance);
 
         Ok(())
     }
+
+    fn journal_record(&self) -> JournalRecord {
+        JournalRecord::Deposit {
+            account: self.account.clone(),
+            amount: self.amount,
+        }
+    }
+
+    fn inverse(&self) -> Box<dyn Transaction> {
+        Box::new(Withdraw {
+            account: self.account.clone(),
+            amount: self.amount,
+        })
+    }
+}
+
+/// Debits the customer; the money is paid out of the bank's cash account
+pub struct Withdraw {
+    pub account: String,
+    pub amount: Money,
+}
+
+impl Transaction for Withdraw {
+    fn post(&self, storage: &mut Storage) -> Result<(), BankError> {
+        self.amount.ensure_positive()?;
+        let balance = storage
+            .customer_balance(&self.account, AccountSide::Source)?
+            .checked_sub(self.amount)?;
+        storage.check_debit(&self.account, balance)?;
+        let (cash, cash_balance) = storage.cash_after(self.amount.checked_neg()?)?;
+
+        storage.set_balance_internal(&self.account, balance);
+        storage.set_balance_internal(&cash, cash_balance);
+
+        Ok(())
+    }
+
+    fn journal_record(&self) -> JournalRecord {
+        JournalRecord::Withdraw {
+            account: self.account.clone(),
+            amount: self.amount,
+        }
+    }
+
+    fn inverse(&self) -> Box<dyn Transaction> {
+        Box::new(Deposit {
+            account: self.account.clone(),
+            amount: self.amount,
+        })
+    }
 }
 
 pub struct Transfer {
     pub from: String,
     pub to: String,
-    pub amount: i64,
+    pub amount: Money,
 }
 
 impl Transaction for Transfer {
-    fn apply(&self, storage: &mut Storage) -> Result<(), TxError> {
-        let from_balance = storage.accounts.entry(self.from.clone()).or_insert(0);
-        if *from_balance < self.amount {
-            return Err(TxError::InsufficientFunds);
+    fn post(&self, storage: &mut Storage) -> Result<(), BankError> {
+        self.amount.ensure_positive()?;
+        let from_balance = storage.customer_balance(&self.from, AccountSide::Source)?;
+        let to_balance = storage.customer_balance(&self.to, AccountSide::Destination)?;
+        // Деньги между валютами переводит только `Exchange`
+        if to_balance.currency() != from_balance.currency() {
+            return Err(BankError::CurrencyMismatch {
+                expected: from_balance.currency(),
+                found: to_balance.currency(),
+            });
         }
-        *from_balance -= self.amount;
-        *storage.accounts.entry(self.to.clone()).or_insert(0) += self.amount;
+
+        let new_from = from_balance.checked_sub(self.amount)?;
+        // Перевод самому себе не меняет баланс и не расходует лимиты:
+        // проверяется только, хватает ли на счёте денег
+        if self.from == self.to {
+            return storage.check_funds(&self.from, new_from);
+        }
+        storage.check_debit(&self.from, new_from)?;
+        let new_to = to_balance.checked_add(self.amount)?;
+
+        // Все проверки пройдены — только теперь изменяем хранилище
+        storage.set_balance_internal(&self.from, new_from);
+        storage.set_balance_internal(&self.to, new_to);
 
         Ok(())
     }
+
+    fn journal_record(&self) -> JournalRecord {
+        JournalRecord::Transfer {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            amount: self.amount,
+        }
+    }
+
+    fn inverse(&self) -> Box<dyn Transaction> {
+        Box::new(Transfer {
+            from: self.to.clone(),
+            to: self.from.clone(),
+            amount: self.amount,
+        })
+    }
+}
+
+/// Converts money between customer accounts in different currencies:
+/// `amount` leaves `from` and `converted` arrives on `to`. The bank's
+/// exchange position in each currency takes the other side, so both
+/// currencies stay balanced. The rate is kept for the record; replay and
+/// reversal use the recorded amounts.
+pub struct Exchange {
+    pub from: Name,
+    pub to: Name,
+    pub amount: Money,
+    pub converted: Money,
+    pub rate: Rate,
+}
+
+impl Exchange {
+    /// Exchange of `amount` at `rate`, which must convert from the currency
+    /// of `amount`
+    pub fn new(from: Name, to: Name, amount: Money, rate: Rate) -> Result<Exchange, BankError> {
+        Ok(Exchange {
+            from,
+            to,
+            converted: rate.convert(amount)?,
+            amount,
+            rate,
+        })
+    }
+}
+
+/// The bank's exchange position in `currency` after it changes by `change`
+fn position_after(
+    storage: &Storage,
+    currency: Currency,
+    change: Money,
+) -> Result<(Name, Money), BankError> {
+    let position = exchange_account(currency);
+    let balance = storage
+        .get_balance_internal(&position)
+        .unwrap_or(Money::zero(currency))
+        .checked_add(change)?;
+    Ok((position, balance))
+}
+
+impl Transaction for Exchange {
+    fn post(&self, storage: &mut Storage) -> Result<(), BankError> {
+        self.amount.ensure_positive()?;
+        // Сумма меньше минимальной единицы другой валюты обменивается в ноль
+        self.converted.ensure_positive()?;
+        if !self
+            .rate
+            .connects(self.amount.currency(), self.converted.currency())
+        {
+            return Err(BankError::CurrencyMismatch {
+                expected: self.rate.quote,
+                found: self.converted.currency(),
+            });
+        }
+        let from_balance = storage.customer_balance(&self.from, AccountSide::Source)?;
+        let to_balance = storage.customer_balance(&self.to, AccountSide::Destination)?;
+
+        let new_from = from_balance.checked_sub(self.amount)?;
+        storage.check_debit(&self.from, new_from)?;
+        let new_to = to_balance.checked_add(self.converted)?;
+        let (sold, sold_balance) = position_after(storage, self.amount.currency(), self.amount)?;
+        let (bought, bought_balance) = position_after(
+            storage,
+            self.converted.currency(),
+            self.converted.checked_neg()?,
+        )?;
+
+        storage.set_balance_internal(&self.from, new_from);
+        storage.set_balance_internal(&self.to, new_to);
+        storage.set_balance_internal(&sold, sold_balance);
+        storage.set_balance_internal(&bought, bought_balance);
+
+        Ok(())
+    }
+
+    fn journal_record(&self) -> JournalRecord {
+        JournalRecord::Exchange {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            amount: self.amount,
+            converted: self.converted,
+            rate: self.rate,
+        }
+    }
+
+    /// Gives back exactly the amounts exchanged, not a conversion at today's rate
+    fn inverse(&self) -> Box<dyn Transaction> {
+        Box::new(Exchange {
+            from: self.to.clone(),
+            to: self.from.clone(),
+            amount: self.converted,
+            converted: self.amount,
+            rate: self.rate,
+        })
+    }
+}
+
+/// A general ledger entry: debits one account and credits another by the
+/// same amount. Either side may be one of the bank's own accounts, which is
+/// opened by its first booking.
+pub struct Booking {
+    pub debit: Name,
+    pub credit: Name,
+    pub amount: Money,
+}
+
+impl Booking {
+    /// Charges a fee: the customer pays it into the bank's fee income
+    pub fn fee(account: Name, amount: Money) -> Booking {
+        Booking {
+            debit: account,
+            credit: fee_account(amount.currency()),
+            amount,
+        }
+    }
+
+    /// Pays interest to the customer out of the bank's interest expense
+    pub fn interest(account: Name, amount: Money) -> Booking {
+        Booking {
+            debit: interest_account(amount.currency()),
+            credit: account,
+            amount,
+        }
+    }
+
+    fn balance(
+        &self,
+        storage: &Storage,
+        name: &Name,
+        side: AccountSide,
+    ) -> Result<Money, BankError> {
+        match storage.get_balance_internal(name) {
+            Some(balance) => {
+                storage.check_status(name, side)?;
+                Ok(balance)
+            }
+            None if is_internal(name) && account_type(name).is_some() => {
+                Ok(Money::zero(self.amount.currency()))
+            }
+            None => Err(BankError::InvalidAccount {
+                side,
+                name: name.clone(),
+            }),
+        }
+    }
+
+    /// Balance of `name` after it is put on the given side of the entry
+    fn after(
+        &self,
+        storage: &Storage,
+        name: &Name,
+        balance: Money,
+        side: Side,
+    ) -> Result<Money, BankError> {
+        if normal_side(name) == side {
+            return balance.checked_add(self.amount);
+        }
+        let balance = balance.checked_sub(self.amount)?;
+        // Клиент уходит в минус только в пределах овердрафта,
+        // а счета банка знака не ограничивают
+        storage.check_debit(name, balance)?;
+        Ok(balance)
+    }
+}
+
+impl Transaction for Booking {
+    fn post(&self, storage: &mut Storage) -> Result<(), BankError> {
+        self.amount.ensure_positive()?;
+        let debit_balance = self.balance(storage, &self.debit, AccountSide::Source)?;
+        let credit_balance = self.balance(storage, &self.credit, AccountSide::Destination)?;
+
+        let new_debit = self.after(storage, &self.debit, debit_balance, Side::Debit)?;
+        let new_credit = if self.debit == self.credit {
+            new_debit
+        } else {
+            credit_balance
+        };
+        let new_credit = self.after(storage, &self.credit, new_credit, Side::Credit)?;
+
+        storage.set_balance_internal(&self.debit, new_debit);
+        storage.set_balance_internal(&self.credit, new_credit);
+
+        Ok(())
+    }
+
+    fn journal_record(&self) -> JournalRecord {
+        JournalRecord::Booking {
+            debit: self.debit.clone(),
+            credit: self.credit.clone(),
+            amount: self.amount,
+        }
+    }
+
+    fn inverse(&self) -> Box<dyn Transaction> {
+        Box::new(Booking {
+            debit: self.credit.clone(),
+            credit: self.debit.clone(),
+            amount: self.amount,
+        })
+    }
+}
+
+/// Settles a hold: takes `amount` of the money reserved on `account` out of
+/// it, paying it out like a `Withdraw` or to `to` like a `Transfer`.
+/// Whatever the capture leaves of the hold is released.
+pub struct Capture {
+    pub hold: HoldId,
+    /// The account the hold was placed on
+    pub account: Name,
+    pub amount: Money,
+    pub to: Option<Name>,
+}
+
+impl Capture {
+    fn settlement(&self) -> Box<dyn Transaction> {
+        match &self.to {
+            Some(to) => Box::new(Transfer {
+                from: self.account.clone(),
+                to: to.clone(),
+                amount: self.amount,
+            }),
+            None => Box::new(Withdraw {
+                account: self.account.clone(),
+                amount: self.amount,
+            }),
+        }
+    }
+}
+
+impl Transaction for Capture {
+    fn post(&self, storage: &mut Storage) -> Result<(), BankError> {
+        self.amount.ensure_positive()?;
+        let hold = storage
+            .get_hold(self.hold)
+            .filter(|hold| hold.account == self.account)
+            .ok_or(BankError::HoldNotFound(self.hold))?;
+        if !hold.is_active(storage.now()) {
+            return Err(BankError::HoldExpired(self.hold));
+        }
+        if hold.amount.checked_sub(self.amount)?.is_negative() {
+            return Err(BankError::InvalidAmount(format!(
+                "списание {} больше заблокированных {}",
+                self.amount, hold.amount
+            )));
+        }
+
+        // Блокировка снимается до списания, чтобы не мешать ему; если списание
+        // не пройдёт, она вернётся вместе с откатом
+        let savepoint = storage.savepoint();
+        storage.release_internal(self.hold)?;
+        match self.settlement().post(storage) {
+            Ok(()) => {
+                storage.release();
+                Ok(())
+            }
+            Err(e) => {
+                storage.rollback_to(savepoint);
+                Err(e)
+            }
+        }
+    }
+
+    fn journal_record(&self) -> JournalRecord {
+        JournalRecord::Capture {
+            hold: self.hold,
+            account: self.account.clone(),
+            amount: self.amount,
+            to: self.to.clone(),
+        }
+    }
+
+    /// Returns the money; the hold is not placed again
+    fn inverse(&self) -> Box<dyn Transaction> {
+        self.settlement().inverse()
+    }
+}
+
+/// Applies several transactions as one unit: if any step fails, every
+/// earlier step is rolled back and `Storage` is left untouched
+pub struct Batch {
+    pub steps: Vec<Box<dyn Transaction>>,
+}
+
+impl Batch {
+    pub fn new(steps: Vec<Box<dyn Transaction>>) -> Self {
+        Batch { steps }
+    }
+}
+
+impl Transaction for Batch {
+    fn post(&self, storage: &mut Storage) -> Result<(), BankError> {
+        let savepoint = storage.savepoint();
+        for (index, step) in self.steps.iter().enumerate() {
+            if let Err(e) = step.post(storage) {
+                storage.rollback_to(savepoint);
+                return Err(BankError::BatchFailed {
+                    step: index + 1,
+                    source: Box::new(e),
+                });
+            }
+        }
+        storage.release();
+
+        Ok(())
+    }
+
+    fn journal_record(&self) -> JournalRecord {
+        // Вложенный пакет атомарен вместе с внешним, поэтому его шаги
+        // записываются в общий плоский список
+        let mut records = Vec::new();
+        for step in &self.steps {
+            match step.journal_record() {
+                JournalRecord::Batch(nested) => records.extend(nested),
+                record => records.push(record),
+            }
+        }
+        JournalRecord::Batch(records)
+    }
+
+    fn inverse(&self) -> Box<dyn Transaction> {
+        // Шаги отменяются в обратном порядке
+        Box::new(Batch::new(
+            self.steps.iter().rev().map(|step| step.inverse()).collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        account::LimitKind,
+        balance_manager::BalanceManager,
+        clock::{FixedClock, Timestamp},
+        money::{Currency, rub},
+        user_manager::UserManager,
+    };
+
+    fn storage_with(accounts: &[(&str, i64)]) -> Storage {
+        let mut storage = Storage::new();
+        for (name, balance) in accounts {
+            UserManager::add_user(&mut storage, name.to_string()).unwrap();
+            if *balance > 0 {
+                BalanceManager::deposit(&mut storage, &name.to_string(), rub(*balance)).unwrap();
+            }
+        }
+        storage
+    }
+
+    #[test]
+    fn test_deposit_rejects_non_positive_amount() {
+        let mut storage = storage_with(&[("Alice", 100)]);
+
+        for amount in [rub(0), rub(-50)] {
+            let tx = Deposit {
+                account: "Alice".to_string(),
+                amount,
+            };
+            assert!(matches!(
+                tx.apply(&mut storage),
+                Err(BankError::NonPositiveAmount(a)) if a == amount
+            ));
+        }
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(100))
+        );
+    }
+
+    #[test]
+    fn test_withdraw() {
+        let mut storage = storage_with(&[("Alice", 100)]);
+        let withdraw = |amount| Withdraw {
+            account: "Alice".to_string(),
+            amount,
+        };
+
+        withdraw(rub(30)).apply(&mut storage).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(70))
+        );
+
+        assert!(matches!(
+            withdraw(rub(71)).apply(&mut storage),
+            Err(BankError::InsufficientFunds(_))
+        ));
+        assert!(matches!(
+            withdraw(rub(-1)).apply(&mut storage),
+            Err(BankError::NonPositiveAmount(_))
+        ));
+        assert!(matches!(
+            Withdraw {
+                account: "Nobody".to_string(),
+                amount: rub(1),
+            }
+            .apply(&mut storage),
+            Err(BankError::InvalidAccount {
+                side: AccountSide::Source,
+                ..
+            })
+        ));
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(70))
+        );
+    }
+
+    #[test]
+    fn test_transfer_rejects_non_positive_amount() {
+        let mut storage = storage_with(&[("Alice", 100), ("Bob", 100)]);
+
+        // Отрицательная сумма не должна переводить деньги в обратную сторону
+        for amount in [rub(0), rub(-50)] {
+            let tx = Transfer {
+                from: "Alice".to_string(),
+                to: "Bob".to_string(),
+                amount,
+            };
+            assert!(matches!(
+                tx.apply(&mut storage),
+                Err(BankError::NonPositiveAmount(_))
+            ));
+        }
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(100))
+        );
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Bob".to_string()),
+            Some(rub(100))
+        );
+    }
+
+    #[test]
+    fn test_deposit_does_not_create_account() {
+        let mut storage = storage_with(&[("Alice", 0)]);
+        let tx = Deposit {
+            account: "Alcie".to_string(),
+            amount: rub(10),
+        };
+
+        assert!(matches!(
+            tx.apply(&mut storage),
+            Err(BankError::InvalidAccount {
+                side: AccountSide::Destination,
+                name,
+            }) if name == "Alcie"
+        ));
+        assert_eq!(storage.get_all().count(), 1);
+    }
+
+    #[test]
+    fn test_transfer_validates_both_accounts() {
+        let mut storage = storage_with(&[("Alice", 100)]);
+
+        let tx = Transfer {
+            from: "Alice".to_string(),
+            to: "Bbo".to_string(),
+            amount: rub(50),
+        };
+        assert!(matches!(
+            tx.apply(&mut storage),
+            Err(BankError::InvalidAccount {
+                side: AccountSide::Destination,
+                ..
+            })
+        ));
+
+        let tx = Transfer {
+            from: "Nobody".to_string(),
+            to: "Alice".to_string(),
+            amount: rub(50),
+        };
+        assert!(matches!(
+            tx.apply(&mut storage),
+            Err(BankError::InvalidAccount {
+                side: AccountSide::Source,
+                ..
+            })
+        ));
+
+        // Хранилище не изменилось и лишние счета не появились
+        assert_eq!(
+            storage.get_all().filter(|a| !is_internal(&a.name)).count(),
+            1
+        );
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(100))
+        );
+    }
+
+    #[test]
+    fn test_failed_transfer_leaves_storage_untouched() {
+        let mut storage = storage_with(&[("Alice", 10), ("Bob", 0)]);
+        let tx = Transfer {
+            from: "Alice".to_string(),
+            to: "Bob".to_string(),
+            amount: rub(50),
+        };
+
+        assert!(matches!(
+            tx.apply(&mut storage),
+            Err(BankError::InsufficientFunds(_))
+        ));
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(10))
+        );
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Bob".to_string()),
+            Some(rub(0))
+        );
+    }
+
+    #[test]
+    fn test_transfer_to_self_keeps_balance() {
+        let mut storage = storage_with(&[("Alice", 10)]);
+        Transfer {
+            from: "Alice".to_string(),
+            to: "Alice".to_string(),
+            amount: rub(5),
+        }
+        .apply(&mut storage)
+        .unwrap();
+
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(10))
+        );
+    }
+
+    #[test]
+    fn test_fee_and_interest_bookings() {
+        let mut storage = storage_with(&[("Alice", 10)]);
+        let alice = "Alice".to_string();
+        let fees = fee_account(Currency::RUB);
+
+        Booking::fee(alice.clone(), rub(4))
+            .apply(&mut storage)
+            .unwrap();
+        assert_eq!(BalanceManager::get_balance(&storage, &alice), Some(rub(6)));
+        assert_eq!(BalanceManager::get_balance(&storage, &fees), Some(rub(4)));
+
+        // Комиссия не может увести клиента в минус
+        assert!(matches!(
+            Booking::fee(alice.clone(), rub(7)).apply(&mut storage),
+            Err(BankError::InsufficientFunds(name)) if name == alice
+        ));
+        assert!(matches!(
+            Booking::fee("Nobody".to_string(), rub(1)).apply(&mut storage),
+            Err(BankError::InvalidAccount {
+                side: AccountSide::Source,
+                ..
+            })
+        ));
+
+        let receipt = Booking::interest(alice.clone(), rub(2))
+            .apply(&mut storage)
+            .unwrap();
+        assert_eq!(BalanceManager::get_balance(&storage, &alice), Some(rub(8)));
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &interest_account(Currency::RUB)),
+            Some(rub(2))
+        );
+
+        storage.reverse(receipt.id, None).unwrap();
+        assert_eq!(BalanceManager::get_balance(&storage, &alice), Some(rub(6)));
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &interest_account(Currency::RUB)),
+            Some(rub(0))
+        );
+    }
+
+    #[test]
+    fn test_batch_applies_all_steps() {
+        let mut storage = storage_with(&[("Company", 100), ("Alice", 0), ("Bob", 0)]);
+        let payroll = Batch::new(vec![
+            Box::new(Withdraw {
+                account: "Company".to_string(),
+                amount: rub(10),
+            }),
+            Box::new(Transfer {
+                from: "Company".to_string(),
+                to: "Alice".to_string(),
+                amount: rub(40),
+            }),
+            Box::new(Transfer {
+                from: "Company".to_string(),
+                to: "Bob".to_string(),
+                amount: rub(50),
+            }),
+        ]);
+
+        payroll.apply(&mut storage).unwrap();
+
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Company".to_string()),
+            Some(rub(0))
+        );
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Bob".to_string()),
+            Some(rub(50))
+        );
+    }
+
+    #[test]
+    fn test_batch_rolls_back_on_failure() {
+        let mut storage = storage_with(&[("Company", 100), ("Alice", 0), ("Bob", 0)]);
+        let payroll = Batch::new(vec![
+            Box::new(Transfer {
+                from: "Company".to_string(),
+                to: "Alice".to_string(),
+                amount: rub(60),
+            }),
+            Box::new(Batch::new(vec![Box::new(Deposit {
+                account: "Bob".to_string(),
+                amount: rub(5),
+            })])),
+            Box::new(Transfer {
+                from: "Company".to_string(),
+                to: "Bob".to_string(),
+                amount: rub(60),
+            }),
+        ]);
+
+        match payroll.apply(&mut storage) {
+            Err(BankError::BatchFailed { step, source }) => {
+                assert_eq!(step, 3);
+                assert!(matches!(*source, BankError::InsufficientFunds(_)));
+            }
+            _ => panic!("ожидалась ошибка BatchFailed"),
+        }
+
+        // Ни один шаг не остался применённым
+        for (name, balance) in [("Company", 100), ("Alice", 0), ("Bob", 0)] {
+            assert_eq!(
+                BalanceManager::get_balance(&storage, &name.to_string()),
+                Some(rub(balance))
+            );
+        }
+
+        assert_eq!(
+            payroll.journal_record().to_string(),
+            "batch;transfer,Company,Alice,60.00 RUB;deposit,Bob,5.00 RUB;\
+             transfer,Company,Bob,60.00 RUB"
+        );
+    }
+
+    #[test]
+    fn test_apply_returns_receipt() {
+        let mut storage = storage_with(&[("Alice", 100), ("Bob", 0)]);
+        let at: Timestamp = "2024-05-01 09:30:00".parse().unwrap();
+        storage.set_clock(FixedClock(at));
+        let deposit = Deposit {
+            account: "Bob".to_string(),
+            amount: rub(5),
+        };
+
+        let first = deposit.apply(&mut storage).unwrap();
+        let second = deposit.apply_with_memo(&mut storage, "счёт 17").unwrap();
+        assert_eq!(second.id, first.id + 1);
+        assert_eq!(second.applied_at, at);
+        assert_eq!(second.memo.as_deref(), Some("счёт 17"));
+        assert_eq!(
+            second.to_string(),
+            format!("транзакция #{} от 2024-05-01 09:30:00 (счёт 17)", second.id)
+        );
+        assert_eq!(storage.history().get(second.id).unwrap().receipt, second);
+
+        // Отклонённая транзакция не получает номер
+        assert!(
+            Withdraw {
+                account: "Bob".to_string(),
+                amount: rub(100),
+            }
+            .apply(&mut storage)
+            .is_err()
+        );
+        assert_eq!(storage.history().next_id(), second.id + 1);
+    }
+
+    #[test]
+    fn test_reverse_transfer_once() {
+        let mut storage = storage_with(&[("Alice", 100), ("Bob", 0)]);
+        let id = Transfer {
+            from: "Alice".to_string(),
+            to: "Bob".to_string(),
+            amount: rub(30),
+        }
+        .apply(&mut storage)
+        .unwrap()
+        .id;
+
+        let reversal = storage.reverse(id, Some("ошибочный перевод")).unwrap().id;
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Alice".to_string()),
+            Some(rub(100))
+        );
+        assert_eq!(
+            storage.history().get(id).unwrap().reversed_by,
+            Some(reversal)
+        );
+        assert_eq!(
+            storage.history().get(reversal).unwrap().record,
+            JournalRecord::Transfer {
+                from: "Bob".to_string(),
+                to: "Alice".to_string(),
+                amount: rub(30),
+            }
+        );
+
+        assert!(matches!(
+            storage.reverse(id, None),
+            Err(BankError::AlreadyReversed { by, .. }) if by == reversal
+        ));
+        assert!(matches!(
+            storage.reverse(42, None),
+            Err(BankError::TransactionNotFound(42))
+        ));
+    }
+
+    #[test]
+    fn test_reversal_ignores_limits_and_freeze() {
+        let mut storage = storage_with(&[("Alice", 100), ("Bob", 0)]);
+        let (alice, bob) = ("Alice".to_string(), "Bob".to_string());
+        let id = Transfer {
+            from: alice.clone(),
+            to: bob.clone(),
+            amount: rub(30),
+        }
+        .apply(&mut storage)
+        .unwrap()
+        .id;
+        UserManager::set_limit(&mut storage, &bob, LimitKind::Daily, Some(rub(10))).unwrap();
+        UserManager::freeze(&mut storage, &bob).unwrap();
+
+        // Отмена возвращает деньги, не упираясь в лимит и заморозку получателя
+        storage.reverse(id, None).unwrap();
+        assert_eq!(BalanceManager::get_balance(&storage, &bob), Some(rub(0)));
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(100))
+        );
+        // Обычное списание со счёта по-прежнему запрещено
+        assert!(matches!(
+            BalanceManager::withdraw(&mut storage, &bob, rub(1)),
+            Err(BankError::AccountUnavailable { .. })
+        ));
+        UserManager::unfreeze(&mut storage, &bob).unwrap();
+        BalanceManager::deposit(&mut storage, &bob, rub(20)).unwrap();
+        assert!(matches!(
+            BalanceManager::withdraw(&mut storage, &bob, rub(11)),
+            Err(BankError::SpendingLimitExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_idempotent_retry_applies_once() {
+        let mut storage = storage_with(&[("Alice", 100), ("Bob", 0)]);
+        let transfer = Transfer {
+            from: "Alice".to_string(),
+            to: "Bob".to_string(),
+            amount: rub(30),
+        };
+
+        let receipt = transfer.apply_idempotent(&mut storage, "order-7").unwrap();
+        let retry = transfer.apply_idempotent(&mut storage, "order-7").unwrap();
+        assert_eq!(retry, receipt);
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &"Bob".to_string()),
+            Some(rub(30))
+        );
+
+        let changed = Transfer {
+            amount: rub(31),
+            ..transfer
+        };
+        assert!(matches!(
+            changed.apply_idempotent(&mut storage, "order-7"),
+            Err(BankError::IdempotencyConflict { original, .. }) if original == receipt.id
+        ));
+    }
 }
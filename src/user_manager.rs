@@ -1,17 +1,130 @@
-use crate::storage::{Name, Storage};
+use std::collections::BTreeMap;
+
+use crate::{
+    account::{Account, AccountId, AccountStatus, LimitKind},
+    customer::{Customer, CustomerId},
+    error::BankError,
+    money::{Currency, Money},
+    storage::{Name, Storage},
+};
 
 pub struct UserManager;
 
 impl UserManager {
-    /// Adds a new user with zero balance
-    /// Returns Some(0) if user was created, None if user already exists
-    pub fn add_user(storage: &mut Storage, name: Name) -> Option<i64> {
+    /// Adds a new customer together with a zero-balance account of the same name
+    /// Returns Ok(zero) if user was created, Err if user already exists
+    pub fn add_user(storage: &mut Storage, name: Name) -> Result<Money, BankError> {
         storage.add_user_internal(name)
     }
 
-    /// Removes a user and returns their final balance
-    /// Returns Some(balance) if user existed, None if user not found
-    pub fn remove_user(storage: &mut Storage, name: &Name) -> Option<i64> {
+    /// Registers a new customer without any accounts
+    /// Returns the customer's ID, Err if the name cannot be stored
+    pub fn add_customer(storage: &mut Storage, name: String) -> Result<CustomerId, BankError> {
+        storage.add_customer_internal(name)
+    }
+
+    pub fn get_customer(storage: &Storage, id: CustomerId) -> Option<&Customer> {
+        storage.get_customer(id)
+    }
+
+    /// Opens an account held by `owners` with zero balance in `currency`;
+    /// several owners make a joint account
+    /// Returns the new account's ID, Err if the name is taken or an owner is unknown
+    pub fn open_account(
+        storage: &mut Storage,
+        name: Name,
+        owners: &[CustomerId],
+        currency: Currency,
+    ) -> Result<AccountId, BankError> {
+        storage.open_account_internal(name, owners.to_vec(), currency)
+    }
+
+    /// Closes an account, keeping it for its history; the owners stay customers.
+    /// Money left on the account goes to `payout`, which is required unless
+    /// the balance is zero.
+    /// Returns the amount paid out, Err if the account is already closed,
+    /// overdrawn or has active holds, or the balance cannot be paid out
+    pub fn close_account(
+        storage: &mut Storage,
+        name: &Name,
+        payout: Option<&Name>,
+    ) -> Result<Money, BankError> {
+        storage.close_account_internal(name, payout)
+    }
+
+    /// Blocks money from leaving the account; deposits still arrive
+    pub fn freeze(storage: &mut Storage, name: &Name) -> Result<(), BankError> {
+        storage.set_status_internal(name, AccountStatus::Frozen)
+    }
+
+    /// Makes a frozen or dormant account active again
+    pub fn unfreeze(storage: &mut Storage, name: &Name) -> Result<(), BankError> {
+        storage.set_status_internal(name, AccountStatus::Active)
+    }
+
+    /// Marks an unused account dormant; it accepts deposits but nothing
+    /// leaves it until `unfreeze`
+    pub fn mark_dormant(storage: &mut Storage, name: &Name) -> Result<(), BankError> {
+        storage.set_status_internal(name, AccountStatus::Dormant)
+    }
+
+    /// Lists the accounts the customer holds, alone or jointly, by account ID
+    pub fn accounts_of(storage: &Storage, customer: CustomerId) -> Vec<&Account> {
+        storage.accounts_of(customer)
+    }
+
+    /// Sums the balances of every account the customer holds, per currency
+    pub fn customer_position(
+        storage: &Storage,
+        customer: CustomerId,
+    ) -> Result<BTreeMap<Currency, Money>, BankError> {
+        storage.customer_position(customer)
+    }
+
+    /// Sets how far below zero the account's balance may go; None removes
+    /// the overdraft. A lower limit does not affect money already owed.
+    pub fn set_overdraft(
+        storage: &mut Storage,
+        name: &Name,
+        limit: Option<Money>,
+    ) -> Result<(), BankError> {
+        let mut limits = storage
+            .get_account(name)
+            .ok_or_else(|| BankError::AccountNotFound(name.clone()))?
+            .limits;
+        limits.overdraft = limit;
+        storage.set_limits_internal(name, limits)
+    }
+
+    /// Sets or, with None, removes one spending limit of the account;
+    /// the bank's default for the currency applies when none is set
+    pub fn set_limit(
+        storage: &mut Storage,
+        name: &Name,
+        kind: LimitKind,
+        limit: Option<Money>,
+    ) -> Result<(), BankError> {
+        let mut limits = storage
+            .get_account(name)
+            .ok_or_else(|| BankError::AccountNotFound(name.clone()))?
+            .limits;
+        match kind {
+            LimitKind::PerTransaction => limits.per_transaction = limit,
+            LimitKind::Daily => limits.daily = limit,
+        }
+        storage.set_limits_internal(name, limits)
+    }
+
+    /// Gets the account with its owner details and settings
+    pub fn get_account<'a>(storage: &'a Storage, name: &Name) -> Option<&'a Account> {
+        storage.get_account(name)
+    }
+
+    /// Removes an active account with zero balance, leaving only its postings;
+    /// use `close_account` to pay out money left on it
+    /// Returns Ok(balance) if user existed, Err if user not found, the account
+    /// is frozen, dormant or closed, has active holds, or its balance is not zero
+    pub fn remove_user(storage: &mut Storage, name: &Name) -> Result<Money, BankError> {
         storage.remove_user_internal(name)
     }
 }
@@ -19,32 +132,504 @@ impl UserManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        account::AccountLimits,
+        balance_manager::BalanceManager,
+        clock::{FixedClock, Timestamp},
+        ledger::cash_account,
+        money::rub,
+        transaction::{Booking, Transaction, Transfer, Withdraw},
+    };
 
     #[test]
     fn test_add_user() {
         let mut storage = Storage::new();
         assert_eq!(
-            UserManager::add_user(&mut storage, "Alice".to_string()),
-            Some(0)
+            UserManager::add_user(&mut storage, "Alice".to_string()).unwrap(),
+            rub(0)
         );
-        assert_eq!(
+        assert!(matches!(
             UserManager::add_user(&mut storage, "Alice".to_string()),
-            None
-        );
+            Err(BankError::DuplicateAccount(name)) if name == "Alice"
+        ));
     }
 
     #[test]
     fn test_remove_user() {
         let mut storage = Storage::new();
-        UserManager::add_user(&mut storage, "Bob".to_string());
+        UserManager::add_user(&mut storage, "Bob".to_string()).unwrap();
 
         assert_eq!(
+            UserManager::remove_user(&mut storage, &"Bob".to_string()).unwrap(),
+            rub(0)
+        );
+        assert!(matches!(
             UserManager::remove_user(&mut storage, &"Bob".to_string()),
-            Some(0)
+            Err(BankError::AccountNotFound(name)) if name == "Bob"
+        ));
+
+        // Остаток не выплачивается из кассы, а замороженный счёт не удаляется
+        let alice = "Alice".to_string();
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(10)).unwrap();
+        assert!(matches!(
+            UserManager::remove_user(&mut storage, &alice),
+            Err(BankError::AccountNotEmpty(_))
+        ));
+        BalanceManager::withdraw(&mut storage, &alice, rub(10)).unwrap();
+        UserManager::freeze(&mut storage, &alice).unwrap();
+        assert!(matches!(
+            UserManager::remove_user(&mut storage, &alice),
+            Err(BankError::AccountUnavailable {
+                status: AccountStatus::Frozen,
+                ..
+            })
+        ));
+        UserManager::close_account(&mut storage, &alice, None).unwrap();
+        assert!(matches!(
+            UserManager::remove_user(&mut storage, &alice),
+            Err(BankError::AccountUnavailable {
+                status: AccountStatus::Closed,
+                ..
+            })
+        ));
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &cash_account(Currency::RUB)),
+            Some(rub(0))
+        );
+    }
+
+    #[test]
+    fn test_open_account_keeps_details() {
+        let mut storage = Storage::new();
+        let opened_at: Timestamp = "2024-05-01 10:00:00".parse().unwrap();
+        storage.set_clock(FixedClock(opened_at));
+        let alice = UserManager::add_customer(&mut storage, "Alice Smith".to_string()).unwrap();
+
+        let id = UserManager::open_account(
+            &mut storage,
+            "alice-eur".to_string(),
+            &[alice],
+            Currency::EUR,
+        )
+        .unwrap();
+        let account = UserManager::get_account(&storage, &"alice-eur".to_string()).unwrap();
+        assert_eq!(account.id, id);
+        assert_eq!(account.owners, [alice]);
+        assert_eq!(account.opened_at, opened_at);
+        assert_eq!(account.status, AccountStatus::Active);
+        assert_eq!(account.balance, Money::zero(Currency::EUR));
+
+        // Номер удалённого счёта не достаётся новому
+        UserManager::remove_user(&mut storage, &"alice-eur".to_string()).unwrap();
+        let reopened = UserManager::open_account(
+            &mut storage,
+            "alice-eur".to_string(),
+            &[alice],
+            Currency::EUR,
+        )
+        .unwrap();
+        assert!(reopened > id);
+        assert!(UserManager::get_customer(&storage, alice).is_some());
+
+        // Имя счёта не должно ломать строки журнала и снимка
+        for invalid in ["", " ", "a,b", "a;b", "a\nb", "a\rb"] {
+            assert!(
+                matches!(
+                    UserManager::open_account(
+                        &mut storage,
+                        invalid.to_string(),
+                        &[alice],
+                        Currency::EUR
+                    ),
+                    Err(BankError::Parse(_))
+                ),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_customer_holds_several_accounts() {
+        let mut storage = Storage::new();
+        let alice = UserManager::add_customer(&mut storage, "Alice".to_string()).unwrap();
+        // add_user по-прежнему заводит клиента с одноимённым счётом
+        UserManager::add_user(&mut storage, "Bob".to_string()).unwrap();
+        let bob = UserManager::get_account(&storage, &"Bob".to_string())
+            .unwrap()
+            .owners[0];
+        assert_eq!(
+            UserManager::get_customer(&storage, bob).unwrap().name,
+            "Bob"
         );
+
+        for (name, owners, currency) in [
+            ("alice-rub", vec![alice], Currency::RUB),
+            ("alice-eur", vec![alice], Currency::EUR),
+            ("family", vec![alice, bob], Currency::RUB),
+        ] {
+            UserManager::open_account(&mut storage, name.to_string(), &owners, currency).unwrap();
+        }
+        BalanceManager::deposit(&mut storage, &"alice-rub".to_string(), rub(10)).unwrap();
+        BalanceManager::deposit(&mut storage, &"family".to_string(), rub(5)).unwrap();
+        BalanceManager::deposit(
+            &mut storage,
+            &"alice-eur".to_string(),
+            Money::from_major(3, Currency::EUR).unwrap(),
+        )
+        .unwrap();
+
+        let names: Vec<&str> = UserManager::accounts_of(&storage, alice)
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(names, ["alice-rub", "alice-eur", "family"]);
+        let position = UserManager::customer_position(&storage, alice).unwrap();
+        assert_eq!(position[&Currency::RUB], rub(15));
         assert_eq!(
-            UserManager::remove_user(&mut storage, &"Bob".to_string()),
-            None
+            position[&Currency::EUR],
+            Money::from_major(3, Currency::EUR).unwrap()
+        );
+        assert_eq!(
+            UserManager::customer_position(&storage, bob).unwrap()[&Currency::RUB],
+            rub(5)
+        );
+
+        assert!(matches!(
+            UserManager::open_account(&mut storage, "x".to_string(), &[99], Currency::RUB),
+            Err(BankError::CustomerNotFound(99))
+        ));
+        assert!(matches!(
+            UserManager::open_account(&mut storage, "x".to_string(), &[], Currency::RUB),
+            Err(BankError::MissingOwner(_))
+        ));
+        assert!(UserManager::add_customer(&mut storage, "Carol, Inc".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_frozen_account_accepts_only_credits() {
+        let mut storage = Storage::new();
+        let (alice, bob) = ("Alice".to_string(), "Bob".to_string());
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        UserManager::add_user(&mut storage, bob.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(100)).unwrap();
+
+        UserManager::freeze(&mut storage, &alice).unwrap();
+        let frozen = |result| {
+            matches!(
+                result,
+                Err(BankError::AccountUnavailable {
+                    status: AccountStatus::Frozen,
+                    ..
+                })
+            )
+        };
+        assert!(frozen(BalanceManager::withdraw(
+            &mut storage,
+            &alice,
+            rub(1)
+        )));
+        assert!(frozen(
+            Transfer {
+                from: alice.clone(),
+                to: bob.clone(),
+                amount: rub(1),
+            }
+            .apply(&mut storage)
+            .map(|_| ())
+        ));
+        assert!(frozen(
+            Booking::fee(alice.clone(), rub(1))
+                .apply(&mut storage)
+                .map(|_| ())
+        ));
+        // Зачисления на замороженный счёт проходят
+        BalanceManager::deposit(&mut storage, &alice, rub(5)).unwrap();
+        Booking::interest(alice.clone(), rub(1))
+            .apply(&mut storage)
+            .unwrap();
+
+        UserManager::mark_dormant(&mut storage, &alice).unwrap();
+        assert!(BalanceManager::withdraw(&mut storage, &alice, rub(1)).is_err());
+        UserManager::unfreeze(&mut storage, &alice).unwrap();
+        BalanceManager::withdraw(&mut storage, &alice, rub(6)).unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(100))
+        );
+        assert!(matches!(
+            UserManager::freeze(&mut storage, &cash_account(Currency::RUB)),
+            Err(BankError::ReservedAccount(_))
+        ));
+    }
+
+    #[test]
+    fn test_close_account() {
+        let mut storage = Storage::new();
+        let (alice, bob) = ("Alice".to_string(), "Bob".to_string());
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        UserManager::add_user(&mut storage, bob.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(30)).unwrap();
+
+        assert!(matches!(
+            UserManager::close_account(&mut storage, &alice, None),
+            Err(BankError::AccountNotEmpty(_))
+        ));
+        UserManager::freeze(&mut storage, &alice).unwrap();
+        assert!(UserManager::close_account(&mut storage, &alice, Some(&bob)).is_err());
+        UserManager::unfreeze(&mut storage, &alice).unwrap();
+
+        assert_eq!(
+            UserManager::close_account(&mut storage, &alice, Some(&bob)).unwrap(),
+            rub(30)
+        );
+        let closed = UserManager::get_account(&storage, &alice).unwrap();
+        assert_eq!(
+            (closed.status, closed.balance),
+            (AccountStatus::Closed, rub(0))
+        );
+        assert_eq!(BalanceManager::get_balance(&storage, &bob), Some(rub(30)));
+
+        // Закрытый счёт не принимает ничего и не открывается снова
+        let closed = |result: Result<(), BankError>| {
+            matches!(
+                result,
+                Err(BankError::AccountUnavailable {
+                    status: AccountStatus::Closed,
+                    ..
+                })
+            )
+        };
+        assert!(closed(BalanceManager::deposit(
+            &mut storage,
+            &alice,
+            rub(1)
+        )));
+        assert!(closed(
+            Transfer {
+                from: bob.clone(),
+                to: alice.clone(),
+                amount: rub(1),
+            }
+            .apply(&mut storage)
+            .map(|_| ())
+        ));
+        assert!(closed(UserManager::unfreeze(&mut storage, &alice)));
+        assert!(matches!(
+            UserManager::add_user(&mut storage, alice.clone()),
+            Err(BankError::DuplicateAccount(_))
+        ));
+
+        // Пустой счёт закрывается без счёта для выплаты
+        assert_eq!(
+            UserManager::close_account(&mut storage, &bob, None).map_err(|e| e.to_string()),
+            Err("На счёте Bob остались средства, укажите счёт для выплаты остатка".to_string())
+        );
+        BalanceManager::withdraw(&mut storage, &bob, rub(30)).unwrap();
+        assert_eq!(
+            UserManager::close_account(&mut storage, &bob, None).unwrap(),
+            rub(0)
+        );
+        assert!(storage.trial_balance().unwrap().is_balanced());
+    }
+
+    #[test]
+    fn test_overdraft_limit() {
+        let mut storage = Storage::new();
+        let (alice, bob) = ("Alice".to_string(), "Bob".to_string());
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        UserManager::add_user(&mut storage, bob.clone()).unwrap();
+        BalanceManager::deposit(&mut storage, &alice, rub(10)).unwrap();
+        assert!(matches!(
+            BalanceManager::withdraw(&mut storage, &alice, rub(11)),
+            Err(BankError::InsufficientFunds(_))
+        ));
+
+        UserManager::set_overdraft(&mut storage, &alice, Some(rub(50))).unwrap();
+        BalanceManager::withdraw(&mut storage, &alice, rub(30)).unwrap();
+        Transfer {
+            from: alice.clone(),
+            to: bob.clone(),
+            amount: rub(20),
+        }
+        .apply(&mut storage)
+        .unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(-40))
+        );
+
+        // Любое списание упирается в один и тот же лимит
+        let exceeded = |result: Result<(), BankError>| {
+            matches!(
+                result,
+                Err(BankError::OverdraftExceeded { limit, .. }) if limit == rub(50)
+            )
+        };
+        assert!(exceeded(BalanceManager::withdraw(
+            &mut storage,
+            &alice,
+            rub(11)
+        )));
+        assert!(exceeded(
+            Withdraw {
+                account: alice.clone(),
+                amount: rub(11),
+            }
+            .apply(&mut storage)
+            .map(|_| ())
+        ));
+        assert!(exceeded(
+            Booking::fee(alice.clone(), rub(11))
+                .apply(&mut storage)
+                .map(|_| ())
+        ));
+        Booking::fee(alice.clone(), rub(10))
+            .apply(&mut storage)
+            .unwrap();
+
+        // Сниженный лимит не мешает зачислениям на счёт в минусе
+        UserManager::set_overdraft(&mut storage, &alice, Some(rub(5))).unwrap();
+        Booking::interest(alice.clone(), rub(1))
+            .apply(&mut storage)
+            .unwrap();
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(-49))
+        );
+        assert!(storage.trial_balance().unwrap().is_balanced());
+
+        assert!(matches!(
+            UserManager::set_overdraft(
+                &mut storage,
+                &alice,
+                Some(Money::from_major(5, Currency::EUR).unwrap())
+            ),
+            Err(BankError::CurrencyMismatch { .. })
+        ));
+        assert!(UserManager::set_overdraft(&mut storage, &alice, Some(rub(-5))).is_err());
+
+        // Счёт с долгом не закрывается и не удаляется, а касса не теряет денег
+        let cash = BalanceManager::get_balance(&storage, &cash_account(Currency::RUB));
+        for overdrawn in [
+            UserManager::close_account(&mut storage, &alice, Some(&bob)),
+            UserManager::close_account(&mut storage, &alice, None),
+            UserManager::remove_user(&mut storage, &alice),
+        ] {
+            assert!(matches!(overdrawn, Err(BankError::AccountOverdrawn(name)) if name == alice));
+        }
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &alice),
+            Some(rub(-49))
+        );
+        assert_eq!(
+            BalanceManager::get_balance(&storage, &cash_account(Currency::RUB)),
+            cash
+        );
+    }
+
+    #[test]
+    fn test_spending_limits() {
+        let mut storage = Storage::new();
+        let (alice, bob) = ("Alice".to_string(), "Bob".to_string());
+        UserManager::add_user(&mut storage, alice.clone()).unwrap();
+        UserManager::add_user(&mut storage, bob.clone()).unwrap();
+        let morning: Timestamp = "2024-05-01 09:00:00".parse().unwrap();
+        storage.set_clock(FixedClock(morning));
+        BalanceManager::deposit(&mut storage, &alice, rub(1000)).unwrap();
+
+        // Лимит банка действует, пока у счёта нет своего
+        storage
+            .set_default_limits(
+                Currency::RUB,
+                AccountLimits {
+                    daily: Some(rub(100)),
+                    ..AccountLimits::default()
+                },
+            )
+            .unwrap();
+        UserManager::set_limit(
+            &mut storage,
+            &alice,
+            LimitKind::PerTransaction,
+            Some(rub(50)),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            BalanceManager::withdraw(&mut storage, &alice, rub(60)),
+            Err(BankError::SpendingLimitExceeded {
+                kind: LimitKind::PerTransaction,
+                remaining,
+                ..
+            }) if remaining == rub(50)
+        ));
+        BalanceManager::withdraw(&mut storage, &alice, rub(50)).unwrap();
+        storage.set_clock(FixedClock("2024-05-01 20:00:00".parse().unwrap()));
+        Transfer {
+            from: alice.clone(),
+            to: bob.clone(),
+            amount: rub(30),
+        }
+        .apply(&mut storage)
+        .unwrap();
+        // Перевод самому себе ничего не списывает и лимит не расходует
+        Transfer {
+            from: alice.clone(),
+            to: alice.clone(),
+            amount: rub(100),
+        }
+        .apply(&mut storage)
+        .unwrap();
+        // Зачисление не возвращает израсходованный лимит
+        BalanceManager::deposit(&mut storage, &alice, rub(30)).unwrap();
+        let error = Booking::fee(alice.clone(), rub(21))
+            .apply(&mut storage)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            BankError::SpendingLimitExceeded {
+                kind: LimitKind::Daily,
+                limit,
+                remaining,
+                ..
+            } if limit == rub(100) && remaining == rub(20)
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Превышен дневной лимит 100.00 RUB по счёту Alice, доступно ещё 20.00 RUB"
+        );
+
+        // Окно скользящее: утренние списания выходят из него через сутки
+        storage.set_clock(FixedClock("2024-05-02 09:00:01".parse().unwrap()));
+        BalanceManager::withdraw(&mut storage, &alice, rub(50)).unwrap();
+        assert!(BalanceManager::withdraw(&mut storage, &alice, rub(21)).is_err());
+
+        // Собственный лимит счёта заменяет лимит банка
+        UserManager::set_limit(&mut storage, &alice, LimitKind::Daily, Some(rub(500))).unwrap();
+        BalanceManager::withdraw(&mut storage, &alice, rub(21)).unwrap();
+        BalanceManager::deposit(&mut storage, &bob, rub(200)).unwrap();
+        assert!(matches!(
+            BalanceManager::withdraw(&mut storage, &bob, rub(101)),
+            Err(BankError::SpendingLimitExceeded {
+                kind: LimitKind::Daily,
+                ..
+            })
+        ));
+
+        // Выплата остатка при закрытии счёта не обходит лимиты
+        assert!(matches!(
+            UserManager::close_account(&mut storage, &bob, Some(&alice)),
+            Err(BankError::SpendingLimitExceeded {
+                kind: LimitKind::Daily,
+                ..
+            })
+        ));
+        assert_eq!(
+            UserManager::get_account(&storage, &bob).unwrap().status,
+            AccountStatus::Active
         );
+        assert_eq!(BalanceManager::get_balance(&storage, &bob), Some(rub(230)));
     }
 }